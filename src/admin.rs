@@ -0,0 +1,404 @@
+use crate::artifact::{
+    find_artifact, list_artifacts, list_runs, remove_run, stream_artifact_zip, ArtifactEntry,
+};
+use crate::cache::{list_caches, remove_cache, CacheEntry};
+use crate::file::is_path_component;
+use crate::namespace::Namespace;
+use crate::utils::{format_time, status_response};
+use crate::version::{CompressionMethod, VersionComponents};
+use log::info;
+use serde_derive::{Deserialize, Serialize};
+use warp::http::{Response, StatusCode};
+use warp::hyper::Body;
+use warp::reply::{json, with_status, Json, WithStatus};
+
+#[derive(Serialize, Clone, Debug)]
+struct StatusResponse {
+    status: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct CacheElement {
+    key: String,
+    version: String,
+    size: usize,
+
+    #[serde(rename = "createdAt")]
+    created_at: String,
+
+    #[serde(rename = "lastAccessedAt")]
+    last_accessed_at: String,
+
+    hits: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+
+    #[serde(rename = "versionComponents", skip_serializing_if = "Option::is_none")]
+    version_components: Option<VersionComponents>,
+}
+
+impl From<&CacheEntry> for CacheElement {
+    fn from(entry: &CacheEntry) -> CacheElement {
+        CacheElement {
+            key: entry.key.clone(),
+            version: entry.version.clone(),
+            size: entry.metadata.size,
+            created_at: format_time(entry.metadata.created_at),
+            last_accessed_at: format_time(entry.metadata.last_accessed_at),
+            hits: entry.metadata.hits,
+            sha256: entry.metadata.sha256.clone(),
+            version_components: entry.metadata.version_components.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct CacheArrayResponse {
+    status: String,
+    count: usize,
+    size: usize,
+    value: Vec<CacheElement>,
+}
+
+fn cache_array_response(entries: &[CacheEntry]) -> CacheArrayResponse {
+    let value: Vec<_> = entries.iter().map(CacheElement::from).collect();
+
+    CacheArrayResponse {
+        status: "success".to_string(),
+        count: value.len(),
+        size: value.iter().map(|x| x.size).sum(),
+        value,
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct PrefixQuery {
+    prefix: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct CacheVersionQuery {
+    version: Option<String>,
+}
+
+// GET /admin/caches?prefix=
+pub fn admin_list_caches(ns: Namespace, query: PrefixQuery) -> WithStatus<Json> {
+    info!(
+        "[admin_list_caches] namespace = {}, query = {query:?}",
+        ns.name
+    );
+
+    let prefix = query.prefix.unwrap_or_default();
+    let mut entries = list_caches(&ns, &prefix);
+    entries.sort_by(|x, y| (&x.key, &x.version).cmp(&(&y.key, &y.version)));
+
+    let res = cache_array_response(&entries);
+    info!(
+        "[admin_list_caches] response = CacheArrayResponse {{ status: \"success\", count: {}, value: <{} items> }}",
+        res.count, res.count
+    );
+
+    with_status(json(&res), StatusCode::OK)
+}
+
+// GET /admin/caches/<key>?version=
+pub fn admin_inspect_cache(
+    ns: Namespace,
+    key: String,
+    query: CacheVersionQuery,
+) -> WithStatus<Json> {
+    info!(
+        "[admin_inspect_cache] namespace = {}, key = {key}, query = {query:?}",
+        ns.name
+    );
+
+    let entries = select_caches(&ns, &key, query.version.as_deref());
+    if entries.is_empty() {
+        return not_found("admin_inspect_cache");
+    }
+
+    let res = cache_array_response(&entries);
+    info!("[admin_inspect_cache] response = {res:?}");
+
+    with_status(json(&res), StatusCode::OK)
+}
+
+// DELETE /admin/caches/<key>?version=
+pub fn admin_delete_cache(
+    ns: Namespace,
+    key: String,
+    query: CacheVersionQuery,
+) -> WithStatus<Json> {
+    info!(
+        "[admin_delete_cache] namespace = {}, key = {key}, query = {query:?}",
+        ns.name
+    );
+
+    let entries = select_caches(&ns, &key, query.version.as_deref());
+    if entries.is_empty() {
+        return not_found("admin_delete_cache");
+    }
+
+    for entry in &entries {
+        remove_cache(&ns, entry);
+    }
+
+    let res = cache_array_response(&entries);
+    info!("[admin_delete_cache] response = {res:?}");
+
+    with_status(json(&res), StatusCode::OK)
+}
+
+// DELETE /admin/caches?prefix=
+pub fn admin_delete_caches(ns: Namespace, query: PrefixQuery) -> WithStatus<Json> {
+    info!(
+        "[admin_delete_caches] namespace = {}, query = {query:?}",
+        ns.name
+    );
+
+    // refuse to wipe everything by accident; "?prefix=" explicitly selects all
+    let Some(prefix) = query.prefix else {
+        let res = StatusResponse {
+            status: "prefix is required".to_string(),
+        };
+        info!("[admin_delete_caches] response = {res:?}");
+
+        return with_status(json(&res), StatusCode::BAD_REQUEST);
+    };
+
+    let entries = list_caches(&ns, &prefix);
+    for entry in &entries {
+        remove_cache(&ns, entry);
+    }
+
+    let res = cache_array_response(&entries);
+    info!(
+        "[admin_delete_caches] response = CacheArrayResponse {{ status: \"success\", count: {}, value: <{} items> }}",
+        res.count, res.count
+    );
+
+    with_status(json(&res), StatusCode::OK)
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct VersionElement {
+    #[serde(rename = "compressionMethod")]
+    compression_method: CompressionMethod,
+
+    version: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct VersionArrayResponse {
+    status: String,
+    value: Vec<VersionElement>,
+}
+
+// POST /admin/version body = { paths, compressionMethod?, os?, enableCrossOsArchive? }
+pub fn admin_cache_version(ns: Namespace, input: VersionComponents) -> WithStatus<Json> {
+    info!(
+        "[admin_cache_version] namespace = {}, input = {input:?}",
+        ns.name
+    );
+
+    if input.paths.is_empty() {
+        let res = StatusResponse {
+            status: "paths are required".to_string(),
+        };
+        info!("[admin_cache_version] response = {res:?}");
+
+        return with_status(json(&res), StatusCode::BAD_REQUEST);
+    }
+
+    // all compression methods unless specified, as the client may not know which one the runner used
+    let value = input
+        .versions()
+        .into_iter()
+        .map(|(compression_method, version)| VersionElement {
+            compression_method,
+            version,
+        })
+        .collect();
+    let res = VersionArrayResponse {
+        status: "success".to_string(),
+        value,
+    };
+    info!("[admin_cache_version] response = {res:?}");
+
+    with_status(json(&res), StatusCode::OK)
+}
+
+fn select_caches(ns: &Namespace, key: &str, version: Option<&str>) -> Vec<CacheEntry> {
+    let mut entries: Vec<_> = list_caches(ns, key)
+        .into_iter()
+        .filter(|x| x.key == key && version.is_none_or(|v| x.version == v))
+        .collect();
+    entries.sort_by(|x, y| x.version.cmp(&y.version));
+    entries
+}
+
+fn not_found(name: &str) -> WithStatus<Json> {
+    let res = StatusResponse {
+        status: "not found".to_string(),
+    };
+    info!("[{name}] response = {res:?}");
+
+    with_status(json(&res), StatusCode::NOT_FOUND)
+}
+
+fn invalid_run_id(name: &str) -> WithStatus<Json> {
+    let res = StatusResponse {
+        status: "invalid run id".to_string(),
+    };
+    info!("[{name}] response = {res:?}");
+
+    with_status(json(&res), StatusCode::BAD_REQUEST)
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct RunElement {
+    #[serde(rename = "runId")]
+    run_id: String,
+
+    count: usize,
+    size: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct RunArrayResponse {
+    status: String,
+    count: usize,
+    value: Vec<RunElement>,
+}
+
+// GET /admin/artifacts
+pub fn admin_list_runs(ns: Namespace) -> WithStatus<Json> {
+    info!("[admin_list_runs] namespace = {}", ns.name);
+
+    let mut array = Vec::new();
+    for run_id in list_runs(&ns) {
+        let artifacts = list_artifacts(&ns, &run_id);
+        array.push(RunElement {
+            run_id,
+            count: artifacts.len(),
+            size: artifacts.iter().map(|x| x.size).sum(),
+        });
+    }
+
+    let count = array.len();
+    let res = RunArrayResponse {
+        status: "success".to_string(),
+        count,
+        value: array,
+    };
+    info!("[admin_list_runs] response = RunArrayResponse {{ status: \"success\", count: {count}, value: <{count} items> }}");
+
+    with_status(json(&res), StatusCode::OK)
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ArtifactElement {
+    name: String,
+    size: usize,
+
+    #[serde(rename = "fileCount")]
+    file_count: usize,
+
+    #[serde(rename = "createdAt")]
+    created_at: String,
+
+    files: Vec<String>,
+}
+
+impl From<ArtifactEntry> for ArtifactElement {
+    fn from(entry: ArtifactEntry) -> ArtifactElement {
+        ArtifactElement {
+            name: entry.name,
+            size: entry.size,
+            file_count: entry.files.len(),
+            created_at: format_time(entry.created_at),
+            files: entry.files,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ArtifactArrayResponse {
+    status: String,
+    count: usize,
+    value: Vec<ArtifactElement>,
+}
+
+// GET /admin/artifacts/<run_id>
+pub fn admin_list_artifacts(ns: Namespace, run_id: String) -> WithStatus<Json> {
+    info!(
+        "[admin_list_artifacts] namespace = {}, run_id = {run_id}",
+        ns.name
+    );
+
+    if !is_path_component(&run_id) {
+        return invalid_run_id("admin_list_artifacts");
+    }
+
+    let artifacts = list_artifacts(&ns, &run_id);
+    if artifacts.is_empty() {
+        return not_found("admin_list_artifacts");
+    }
+
+    let array: Vec<_> = artifacts.into_iter().map(ArtifactElement::from).collect();
+
+    let count = array.len();
+    let res = ArtifactArrayResponse {
+        status: "success".to_string(),
+        count,
+        value: array,
+    };
+    info!("[admin_list_artifacts] response = ArtifactArrayResponse {{ status: \"success\", count: {count}, value: <{count} items> }}");
+
+    with_status(json(&res), StatusCode::OK)
+}
+
+// DELETE /admin/artifacts/<run_id>
+pub fn admin_delete_run(ns: Namespace, run_id: String) -> WithStatus<Json> {
+    info!(
+        "[admin_delete_run] namespace = {}, run_id = {run_id}",
+        ns.name
+    );
+
+    if !is_path_component(&run_id) {
+        return invalid_run_id("admin_delete_run");
+    }
+
+    if !remove_run(&ns, &run_id) {
+        return not_found("admin_delete_run");
+    }
+
+    let res = StatusResponse {
+        status: "success".to_string(),
+    };
+    info!("[admin_delete_run] response = {res:?}");
+
+    with_status(json(&res), StatusCode::OK)
+}
+
+// GET /admin/artifacts/<run_id>/<name>/zip
+pub fn admin_download_artifact(ns: Namespace, run_id: String, name: String) -> Response<Body> {
+    info!(
+        "[admin_download_artifact] namespace = {}, run_id = {run_id}, name = {name}",
+        ns.name
+    );
+
+    if !is_path_component(&run_id) || !is_path_component(&name) {
+        info!("[admin_download_artifact] response = <invalid run id or artifact name>");
+        return status_response(StatusCode::BAD_REQUEST, "invalid run id or artifact name")
+            .map(Body::from);
+    }
+
+    let Some(entry) = find_artifact(&ns, &run_id, &name) else {
+        info!("[admin_download_artifact] response = <not found>");
+        return status_response(StatusCode::NOT_FOUND, "not found").map(Body::from);
+    };
+
+    stream_artifact_zip(ns, entry, "admin_download_artifact")
+}
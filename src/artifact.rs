@@ -1,9 +1,18 @@
 use crate::file::*;
-use crate::utils::{glob_in, parse_range};
-use log::info;
+use crate::logging::{record_bytes, record_run_id};
+use crate::metrics::{DOWNLOADED_BYTES, EVICTIONS, FINALIZE_DURATION, UPLOADED_BYTES};
+use crate::namespace::Namespace;
+use crate::shutdown::{begin_upload, end_upload, is_draining, touch_upload};
+use crate::utils::{format_time, glob_in, now, parse_range, status_response};
+use log::{error, info};
 use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::Path;
+use tokio::runtime::Handle;
 use warp::http::{Response, StatusCode};
-use warp::hyper::body::Bytes;
+use warp::hyper::body::{Bytes, Sender};
+use warp::hyper::Body;
 use warp::path::Tail;
 use warp::reply::{json, with_status, Json, WithStatus};
 
@@ -13,6 +22,16 @@ pub struct VersionQuery {
     api_version: String,
 }
 
+// GET "/<run_id>/artifacts?api-version&artifactName"
+#[derive(Deserialize, Clone, Debug)]
+pub struct ListArtifactsQuery {
+    #[serde(rename = "api-version")]
+    api_version: String,
+
+    #[serde(rename = "artifactName")]
+    name: Option<String>,
+}
+
 #[derive(Serialize, Clone, Debug)]
 struct StatusResponse {
     status: String,
@@ -29,29 +48,198 @@ fn unsupported_version() -> WithStatus<Json> {
     )
 }
 
+// as on GitHub, unless the client asks for another retention
+const DEFAULT_RETENTION_DAYS: u64 = 90;
+
+// the range accepted by GitHub for public repositories
+const MAX_RETENTION_DAYS: u64 = 90;
+
+fn expires_at(created_at: u64, retention_days: u64) -> u64 {
+    created_at.saturating_add(retention_days.saturating_mul(24 * 60 * 60))
+}
+
+// POST "/<run_id>/artifacts?api-version"
+#[derive(Deserialize, Clone, Debug)]
+pub struct CreateContainerInput {
+    #[serde(rename = "Type", default = "default_container_type")]
+    kind: String,
+
+    #[serde(rename = "Name")]
+    name: String,
+
+    #[serde(rename = "RetentionDays")]
+    retention_days: Option<u64>,
+}
+
+fn default_container_type() -> String {
+    "actions_storage".to_string()
+}
+
+// saved as "<run_id>/<name>.containerMetadata" when the container is created
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct ContainerMetadata {
+    #[serde(rename = "containerId")]
+    container_id: u64,
+
+    #[serde(rename = "type")]
+    kind: String,
+
+    #[serde(rename = "createdAt")]
+    created_at: u64,
+
+    #[serde(rename = "expiresAt")]
+    expires_at: u64,
+}
+
+impl ContainerMetadata {
+    fn new(ns: &Namespace, run_id: &str, name: &str, kind: &str, retention_days: u64) -> Self {
+        // stable across restarts, and representable in javascript numbers
+        let hash = Sha256::digest(format!("{}/{run_id}/{name}", ns.name));
+        let mut bytes = [0; 8];
+        bytes[2..].copy_from_slice(&hash[..6]);
+
+        let created_at = now();
+        ContainerMetadata {
+            container_id: u64::from_be_bytes(bytes),
+            kind: kind.to_string(),
+            created_at,
+            expires_at: expires_at(created_at, retention_days),
+        }
+    }
+}
+
+fn container_path(ns: &Namespace, run_id: &str, name: &str) -> String {
+    format!("{}/{run_id}/{name}{CONTAINER_SUFFIX}", ns.artifacts())
+}
+
+fn load_container(ns: &Namespace, entry: &ArtifactEntry) -> ContainerMetadata {
+    // artifacts uploaded by older versions, or by clients not creating a container, have none saved
+    let path = container_path(ns, &entry.run_id, &entry.name);
+    std::fs::read(path)
+        .ok()
+        .and_then(|x| serde_json::from_slice(&x).ok())
+        .unwrap_or_else(|| {
+            let mut container = ContainerMetadata::new(
+                ns,
+                &entry.run_id,
+                &entry.name,
+                &default_container_type(),
+                DEFAULT_RETENTION_DAYS,
+            );
+            container.created_at = entry.created_at;
+            container.expires_at = expires_at(entry.created_at, DEFAULT_RETENTION_DAYS);
+            container
+        })
+}
+
+fn save_container(ns: &Namespace, run_id: &str, name: &str, container: &ContainerMetadata) {
+    std::fs::create_dir_all(format!("{}/{run_id}", ns.artifacts())).unwrap();
+    let json = serde_json::to_vec(container).unwrap();
+    std::fs::write(container_path(ns, run_id, name), json).unwrap();
+}
+
+fn is_artifact_name(name: &str) -> bool {
+    // a single component; the top-level directory of the run
+    is_path_component(name)
+}
+
+fn invalid_artifact_name(function: &str) -> WithStatus<Json> {
+    let res = StatusResponse {
+        status: "invalid artifact name".to_string(),
+    };
+    info!("[{function}] response = {res:?}");
+
+    with_status(json(&res), StatusCode::BAD_REQUEST)
+}
+
+fn invalid_run_id(function: &str) -> WithStatus<Json> {
+    // run ids become directory names; "..", for one, would reach other namespaces
+    let res = StatusResponse {
+        status: "invalid run id".to_string(),
+    };
+    info!("[{function}] response = {res:?}");
+
+    with_status(json(&res), StatusCode::BAD_REQUEST)
+}
+
 #[derive(Serialize, Clone, Debug)]
-struct UrlResponse {
+struct ContainerResponse {
     status: String,
 
+    #[serde(rename = "containerId")]
+    container_id: u64,
+
+    name: String,
+
+    #[serde(rename = "type")]
+    kind: String,
+
+    // where the files are uploaded to
     #[serde(rename = "fileContainerResourceUrl")]
+    file_container_url: String,
+
+    // where the files are listed, by name
     url: String,
+
+    #[serde(rename = "expiresOn")]
+    expires_on: String,
 }
 
 pub fn get_artifact_upload_url(
-    host: &str,
+    ns: Namespace,
     run_id: String,
     version: VersionQuery,
+    input: CreateContainerInput,
 ) -> WithStatus<Json> {
-    info!("[get_artifact_upload_url] run_id = {run_id}, version = {version:?}");
+    info!("[get_artifact_upload_url] run_id = {run_id}, version = {version:?}, input = {input:?}");
+    record_run_id(&run_id);
+
+    if !is_path_component(&run_id) {
+        return invalid_run_id("get_artifact_upload_url");
+    }
 
     // TODO: unsupported version response
     if version.api_version != "6.0-preview" {
         return unsupported_version();
     }
 
-    let res = UrlResponse {
+    if !is_artifact_name(&input.name) {
+        return invalid_artifact_name("get_artifact_upload_url");
+    }
+
+    let retention_days = input.retention_days.unwrap_or(DEFAULT_RETENTION_DAYS);
+    if !(1..=MAX_RETENTION_DAYS).contains(&retention_days) {
+        let res = StatusResponse {
+            status: format!("retention days must be between 1 and {MAX_RETENTION_DAYS}"),
+        };
+        info!("[get_artifact_upload_url] response = {res:?}");
+
+        return with_status(json(&res), StatusCode::BAD_REQUEST);
+    }
+
+    if is_draining() {
+        let res = StatusResponse {
+            status: "server is shutting down".to_string(),
+        };
+        info!("[get_artifact_upload_url] response = {res:?}");
+
+        return with_status(json(&res), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    // one per artifact; paired with the PATCH that finalizes it
+    begin_upload(&format!("artifact:{}/{run_id}", ns.name));
+
+    let container = ContainerMetadata::new(&ns, &run_id, &input.name, &input.kind, retention_days);
+    save_container(&ns, &run_id, &input.name, &container);
+
+    let res = ContainerResponse {
         status: "success".to_string(),
-        url: format!("{host}/upload/{run_id}"),
+        container_id: container.container_id,
+        kind: container.kind,
+        file_container_url: format!("{}/upload/{run_id}", ns.host),
+        url: format!("{}/artifacts/{run_id}/{}", ns.host, input.name),
+        expires_on: format_time(container.expires_at),
+        name: input.name,
     };
     info!("[get_artifact_upload_url] response = {res:?}");
 
@@ -65,34 +253,63 @@ pub struct ItemPathQuery {
 }
 
 pub fn upload_artifact(
+    ns: Namespace,
     run_id: String,
     path: ItemPathQuery,
     encoding: Option<String>,
     range: Option<String>,
     input: Bytes,
-) -> Json {
+) -> WithStatus<Json> {
     info!(
         "[upload_artifact] run_id = {run_id}, path = {path:?}, range = {range:?}, input = <{} bytes>",
         input.len()
     );
+    record_run_id(&run_id);
+    record_bytes(input.len());
+
+    if !is_path_component(&run_id) {
+        return invalid_run_id("upload_artifact");
+    }
+    if !is_safe_path(&path.path) {
+        let res = StatusResponse {
+            status: "invalid itemPath".to_string(),
+        };
+        info!("[upload_artifact] response = {res:?}");
+
+        return with_status(json(&res), StatusCode::BAD_REQUEST);
+    }
 
     // format chunk prefix that can be safely sorted into the original chunk order
     // (this assumes total bytes being less than 1TB)
     let path = path.path;
-    let path = format!(".act_local_cache/artifacts/{run_id}/{path}");
+    let path = format!("{}/{run_id}/{path}", ns.artifacts());
 
     // workaround for gzipped stream
     let is_gzip = encoding.as_deref() == Some("gzip");
 
     let range = range.as_deref().map_or(0..input.len(), parse_range);
     save_file(&path, is_gzip, range.start, &input.slice(..));
+    touch_upload(&format!("artifact:{}/{run_id}", ns.name));
+    UPLOADED_BYTES
+        .with_label_values(&["upload_artifact"])
+        .inc_by(input.len() as u64);
 
     let res = StatusResponse {
         status: "success".to_string(),
     };
     info!("[upload_artifact] response = {res:?}");
 
-    json(&res)
+    with_status(json(&res), StatusCode::OK)
+}
+
+// PATCH "/<run_id>/artifacts?api-version&artifactName"
+#[derive(Deserialize, Clone, Debug)]
+pub struct FinalizeArtifactQuery {
+    #[serde(rename = "api-version")]
+    api_version: String,
+
+    #[serde(rename = "artifactName")]
+    name: Option<String>,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -102,17 +319,58 @@ pub struct FinalizeQuery {
 }
 
 pub fn finalize_artifact(
+    ns: Namespace,
+    compression: Compression,
     run_id: String,
-    version: VersionQuery,
+    query: FinalizeArtifactQuery,
     input: FinalizeQuery,
 ) -> WithStatus<Json> {
-    info!("[finalize_artifact] run_id = {run_id}, version = {version:?}, input = {input:?}");
+    info!("[finalize_artifact] run_id = {run_id}, query = {query:?}, input = {input:?}");
+    record_run_id(&run_id);
 
-    if version.api_version != "6.0-preview" {
+    if !is_path_component(&run_id) {
+        return invalid_run_id("finalize_artifact");
+    }
+
+    if query.api_version != "6.0-preview" {
         return unsupported_version();
     }
 
-    let size = finalize_files(&format!(".act_local_cache/artifacts/{run_id}"), "**/*");
+    // jobs of a matrix share the run; only the chunks of this artifact are finalized, leaving
+    // the ones other jobs are still uploading. clients not naming it finalize the whole run
+    let pattern = match &query.name {
+        Some(name) if !is_artifact_name(name) => {
+            return invalid_artifact_name("finalize_artifact");
+        }
+        Some(name) => format!("{}/**/*", glob::Pattern::escape(name)),
+        None => "**/*".to_string(),
+    };
+
+    // clients finalizing without creating the container first
+    if let Some(name) = &query.name {
+        if !Path::new(&container_path(&ns, &run_id, name)).exists() {
+            let container = ContainerMetadata::new(
+                &ns,
+                &run_id,
+                name,
+                &default_container_type(),
+                DEFAULT_RETENTION_DAYS,
+            );
+            save_container(&ns, &run_id, name, &container);
+        }
+    }
+
+    let timer = FINALIZE_DURATION
+        .with_label_values(&["artifact"])
+        .start_timer();
+    let size = finalize_files(
+        &format!("{}/{run_id}", ns.artifacts()),
+        &pattern,
+        compression,
+    );
+    timer.observe_duration();
+    end_upload(&format!("artifact:{}/{run_id}", ns.name));
+
     if size != input.size {
         let expected = input.size;
         info!("[finalize_artifact] upload size differs (expected = {expected}, actual = {size})");
@@ -128,51 +386,85 @@ pub fn finalize_artifact(
 }
 
 #[derive(Serialize, Clone, Debug)]
-struct UrlArrayElement {
+struct ArtifactElement {
+    #[serde(rename = "containerId")]
+    container_id: u64,
+
     name: String,
 
+    #[serde(rename = "type")]
+    kind: String,
+
+    // one container per artifact; it may be of another run than the one of the client
     #[serde(rename = "fileContainerResourceUrl")]
+    file_container_url: String,
+
     url: String,
+
+    #[serde(rename = "expiresOn")]
+    expires_on: String,
+
+    size: usize,
+
+    #[serde(rename = "fileCount")]
+    file_count: usize,
+
+    #[serde(rename = "createdAt")]
+    created_at: String,
 }
 
 #[derive(Serialize, Clone, Debug)]
-struct UrlArrayResponse {
+struct ArtifactArrayResponse {
     status: String,
     count: usize,
-    value: Vec<UrlArrayElement>,
+    value: Vec<ArtifactElement>,
 }
 
 pub fn get_artifact_download_url(
-    host: &str,
+    ns: Namespace,
     run_id: String,
-    version: VersionQuery,
+    query: ListArtifactsQuery,
 ) -> WithStatus<Json> {
-    info!("[get_artifact_download_url] run_id = {run_id}, version = {version:?}");
+    info!("[get_artifact_download_url] run_id = {run_id}, query = {query:?}");
+    record_run_id(&run_id);
+
+    if !is_path_component(&run_id) {
+        return invalid_run_id("get_artifact_download_url");
+    }
 
     // TODO: unsupported version response
-    if version.api_version != "6.0-preview" {
+    if query.api_version != "6.0-preview" {
         return unsupported_version();
     }
 
-    let dir = format!(".act_local_cache/artifacts/{run_id}");
-    let paths = glob_in(&dir, "*").unwrap();
-
     let mut array = Vec::new();
-    for path in &paths {
-        let path = path.to_str().unwrap();
-        array.push(UrlArrayElement {
-            name: path.to_string(),
-            url: format!("{host}/download/{run_id}"),
+    for entry in list_artifacts(&ns, &run_id) {
+        if query.name.as_ref().is_some_and(|x| x != &entry.name) {
+            continue;
+        }
+
+        let container = load_container(&ns, &entry);
+        let url = format!("{}/artifacts/{}/{}", ns.host, entry.run_id, entry.name);
+        array.push(ArtifactElement {
+            container_id: container.container_id,
+            kind: container.kind,
+            file_container_url: url.clone(),
+            url,
+            expires_on: format_time(container.expires_at),
+            size: entry.size,
+            file_count: entry.files.len(),
+            created_at: format_time(entry.created_at),
+            name: entry.name,
         });
     }
 
     let count = array.len();
-    let res = UrlArrayResponse {
+    let res = ArtifactArrayResponse {
         status: "success".to_string(),
         count,
         value: array,
     };
-    info!("[get_artifact_download_url] response = PathArrayResponse {{ status: \"success\", count: {count}, value: <{count} items> }}");
+    info!("[get_artifact_download_url] response = ArtifactArrayResponse {{ status: \"success\", count: {count}, value: <{count} items> }}");
 
     with_status(json(&res), StatusCode::OK)
 }
@@ -186,6 +478,9 @@ struct PathArrayElement {
 
     #[serde(rename = "contentLocation")]
     url: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -195,51 +490,421 @@ struct PathArrayResponse {
     value: Vec<PathArrayElement>,
 }
 
-pub fn enumerate_artifacts(host: &str, run_id: String) -> WithStatus<Json> {
-    info!("[enumerate_artifacts] run_id = {run_id}");
+#[derive(Deserialize, Clone, Debug)]
+pub struct ItemPathFilter {
+    #[serde(rename = "itemPath")]
+    path: Option<String>,
+}
 
-    let files = list_all_files(&format!(".act_local_cache/artifacts/{run_id}"));
+fn path_array_response(
+    ns: &Namespace,
+    run_id: &str,
+    files: Vec<String>,
+    filter: &ItemPathFilter,
+) -> PathArrayResponse {
+    // files are relative to the run directory, i.e. "<artifact name>/<path>", as actions/download-artifact expects
+    let dir = format!("{}/{run_id}", ns.artifacts());
 
     let mut array = Vec::new();
     for file in files {
-        let url = format!("{host}/download/{run_id}/{file}");
+        // itemPath selects an artifact (or a directory of it)
+        if let Some(prefix) = &filter.path {
+            let prefix = prefix.trim_end_matches('/');
+            if file != prefix && !file.starts_with(&format!("{prefix}/")) {
+                continue;
+            }
+        }
+
+        let url = format!("{}/download/{run_id}/{file}", ns.host);
+        let sha256 = load_metadata(&format!("{dir}/{file}")).and_then(|x| x.sha256);
         array.push(PathArrayElement {
             path: file,
             item_type: "file".to_string(),
             url,
+            sha256,
         });
     }
 
-    let count = array.len();
-    let res = PathArrayResponse {
+    PathArrayResponse {
         status: "success".to_string(),
-        count,
+        count: array.len(),
         value: array,
-    };
+    }
+}
+
+// GET "/download/<run_id>?itemPath"; the container of all artifacts of a run, as handed out by older versions
+pub fn enumerate_artifacts(
+    ns: Namespace,
+    run_id: String,
+    filter: ItemPathFilter,
+) -> WithStatus<Json> {
+    info!("[enumerate_artifacts] run_id = {run_id}, filter = {filter:?}");
+    record_run_id(&run_id);
+
+    if !is_path_component(&run_id) {
+        return invalid_run_id("enumerate_artifacts");
+    }
+
+    let files = list_all_files(&format!("{}/{run_id}", ns.artifacts()));
+    let res = path_array_response(&ns, &run_id, files, &filter);
+
+    let count = res.count;
     info!("[enumerate_artifacts] response = PathArrayResponse {{ status: \"success\", count: {count}, value: <{count} items> }}");
 
     with_status(json(&res), StatusCode::OK)
 }
 
-pub fn download_artifact(run_id: String, path: Tail, range: Option<String>) -> Response<Vec<u8>> {
-    info!("[download_artifact] run_id = {run_id}, path = {path:?}, range = {range:?}");
+// GET "/artifacts/<run_id>/<name>?itemPath"; the container of an artifact
+pub fn enumerate_artifact_items(
+    ns: Namespace,
+    run_id: String,
+    name: String,
+    filter: ItemPathFilter,
+) -> WithStatus<Json> {
+    info!("[enumerate_artifact_items] run_id = {run_id}, name = {name}, filter = {filter:?}");
+    record_run_id(&run_id);
+
+    if !is_path_component(&run_id) {
+        return invalid_run_id("enumerate_artifact_items");
+    }
 
-    let path = path.as_str();
-    let (is_gzip, data) = dump_file(
-        &format!(".act_local_cache/artifacts/{run_id}/{path}"),
-        range.as_deref().map(parse_range),
-    );
+    let Some(entry) = find_artifact(&ns, &run_id, &name) else {
+        let res = StatusResponse {
+            status: "not found".to_string(),
+        };
+        info!("[enumerate_artifact_items] response = {res:?}");
 
-    // workaround for gzipped stream
+        return with_status(json(&res), StatusCode::NOT_FOUND);
+    };
+
+    let files = entry.files.iter().map(|x| format!("{name}/{x}")).collect();
+    let res = path_array_response(&ns, &run_id, files, &filter);
+
+    let count = res.count;
+    info!("[enumerate_artifact_items] response = PathArrayResponse {{ status: \"success\", count: {count}, value: <{count} items> }}");
+
+    with_status(json(&res), StatusCode::OK)
+}
+
+pub fn download_artifact(
+    ns: Namespace,
+    run_id: String,
+    path: Tail,
+    range: Option<String>,
+    accept_encoding: Option<String>,
+) -> Response<Vec<u8>> {
+    info!("[download_artifact] run_id = {run_id}, path = {path:?}, range = {range:?}, accept_encoding = {accept_encoding:?}");
+    record_run_id(&run_id);
+
+    if !is_path_component(&run_id) || !is_safe_path(path.as_str()) {
+        info!("[download_artifact] response = <invalid path>");
+        return status_response(StatusCode::BAD_REQUEST, "invalid path");
+    }
+
+    let path = format!("{}/{run_id}/{}", ns.artifacts(), path.as_str());
+    let Some(metadata) = load_metadata(&path) else {
+        info!("[download_artifact] response = <not found>");
+        return status_response(StatusCode::NOT_FOUND, "not found");
+    };
+
+    let range = range.as_deref().map(parse_range);
+    let (encoding, data) = match dump_file(&path, range, accept_encoding.as_deref()) {
+        Ok(x) => x,
+        Err(e) => {
+            error!("[download_artifact] {path}: {e}");
+            return status_response(StatusCode::INTERNAL_SERVER_ERROR, "corrupted");
+        }
+    };
+
+    // of the whole file as uploaded
     let header = Response::builder().header("Content-Type", "application/octet-stream");
-    let header = if is_gzip {
-        header.header("Content-Encoding", "gzip")
+    let header = match metadata.sha256 {
+        Some(sha256) => header.header("X-Checksum-Sha256", sha256),
+        None => header,
+    };
+    let header = if let Some(encoding) = encoding {
+        header.header("Content-Encoding", encoding)
     } else {
         header
     };
 
     let len = data.len();
+    record_bytes(len);
+    DOWNLOADED_BYTES
+        .with_label_values(&["download_artifact"])
+        .inc_by(len as u64);
     info!("[download_artifact] response = <{len} bytes>");
 
     header.body(data).unwrap()
 }
+
+// an artifact of a run; the top-level directory of the run holding its files
+#[derive(Clone, Debug)]
+pub struct ArtifactEntry {
+    pub run_id: String,
+    pub name: String,
+    pub files: Vec<String>, // relative to the artifact directory
+    pub size: usize,
+    pub created_at: u64,
+}
+
+pub fn list_runs(ns: &Namespace) -> Vec<String> {
+    let dir = ns.artifacts();
+    let paths = glob_in(&dir, "*").unwrap_or_default();
+
+    let mut array: Vec<_> = paths
+        .iter()
+        .map(|x| x.to_str().unwrap().to_string())
+        .filter(|x| Path::new(&format!("{dir}/{x}")).is_dir())
+        .collect();
+    array.sort();
+    array
+}
+
+pub fn list_artifacts(ns: &Namespace, run_id: &str) -> Vec<ArtifactEntry> {
+    if !is_path_component(run_id) {
+        return Vec::new();
+    }
+
+    let dir = format!("{}/{run_id}", ns.artifacts());
+    let paths = glob_in(&dir, "*").unwrap_or_default();
+
+    let mut array = Vec::new();
+    for path in &paths {
+        let name = path.to_str().unwrap();
+        let artifact_dir = format!("{dir}/{name}");
+        if !Path::new(&artifact_dir).is_dir() {
+            continue;
+        }
+
+        // still being uploaded, or left by an upload that was never finalized
+        let files = list_all_files(&artifact_dir);
+        if files.is_empty() || count_pending_uploads(&artifact_dir) > 0 {
+            continue;
+        }
+
+        let metadata: Vec<_> = files
+            .iter()
+            .filter_map(|x| load_metadata(&format!("{artifact_dir}/{x}")))
+            .collect();
+
+        array.push(ArtifactEntry {
+            run_id: run_id.to_string(),
+            name: name.to_string(),
+            files,
+            size: metadata.iter().map(|x| x.size).sum(),
+            created_at: metadata.iter().map(|x| x.created_at).min().unwrap_or(0),
+        });
+    }
+    array.sort_by(|x, y| x.name.cmp(&y.name));
+    array
+}
+
+pub fn find_artifact(ns: &Namespace, run_id: &str, name: &str) -> Option<ArtifactEntry> {
+    if !is_artifact_name(name) {
+        return None;
+    }
+
+    list_artifacts(ns, run_id)
+        .into_iter()
+        .find(|x| x.name == name)
+}
+
+pub fn write_artifact_zip<W: Write>(
+    ns: &Namespace,
+    entry: &ArtifactEntry,
+    output: W,
+) -> std::io::Result<usize> {
+    // fails if any of the files does not match its checksum
+    let dir = format!("{}/{}/{}", ns.artifacts(), entry.run_id, entry.name);
+    write_zip(&dir, &entry.files, output)
+}
+
+// sends what is written to the body of a response, from a blocking task. the response is aborted
+// unless completed, even by a panic, so that the client never takes a partial archive for a whole
+struct BodyWriter {
+    sender: Option<Sender>,
+    handle: Handle,
+    size: usize,
+    complete: bool,
+}
+
+impl Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let bytes = Bytes::copy_from_slice(buf);
+        let sender = self.sender.as_mut().unwrap();
+        self.handle
+            .block_on(sender.send_data(bytes))
+            .map_err(|_| std::io::Error::new(ErrorKind::BrokenPipe, "client has gone"))?;
+        self.size += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for BodyWriter {
+    fn drop(&mut self) {
+        if !self.complete {
+            if let Some(sender) = self.sender.take() {
+                sender.abort();
+            }
+        }
+    }
+}
+
+pub fn stream_artifact_zip(
+    ns: Namespace,
+    entry: ArtifactEntry,
+    function: &'static str,
+) -> Response<Body> {
+    // the archive is made as it is sent, so its size is not known in advance. a file not matching
+    // its checksum aborts the response halfway, and the client sees a broken archive
+    let (sender, body) = Body::channel();
+    let handle = Handle::current();
+    let name = entry.name.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut writer = BodyWriter {
+            sender: Some(sender),
+            handle,
+            size: 0,
+            complete: false,
+        };
+
+        let output = BufWriter::with_capacity(64 * 1024, &mut writer);
+        match write_artifact_zip(&ns, &entry, output) {
+            Ok(size) => {
+                info!(
+                    "[{function}] sent {} files ({size} bytes before compression)",
+                    entry.files.len()
+                );
+                writer.complete = true;
+            }
+            Err(e) => {
+                error!("[{function}] {e}");
+            }
+        }
+        DOWNLOADED_BYTES
+            .with_label_values(&[function])
+            .inc_by(writer.size as u64);
+    });
+
+    Response::builder()
+        .header("Content-Type", "application/zip")
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{name}.zip\""),
+        )
+        .body(body)
+        .unwrap()
+}
+
+// GET "/artifacts/<run_id>/<name>/zip"
+pub fn download_artifact_zip(ns: Namespace, run_id: String, name: String) -> Response<Body> {
+    info!("[download_artifact_zip] run_id = {run_id}, name = {name}");
+    record_run_id(&run_id);
+
+    if !is_path_component(&run_id) || !is_artifact_name(&name) {
+        info!("[download_artifact_zip] response = <invalid run id or artifact name>");
+        return status_response(StatusCode::BAD_REQUEST, "invalid run id or artifact name")
+            .map(Body::from);
+    }
+
+    let Some(entry) = find_artifact(&ns, &run_id, &name) else {
+        info!("[download_artifact_zip] response = <not found>");
+        return status_response(StatusCode::NOT_FOUND, "not found").map(Body::from);
+    };
+
+    stream_artifact_zip(ns, entry, "download_artifact_zip")
+}
+
+pub fn remove_run(ns: &Namespace, run_id: &str) -> bool {
+    info!("[remove_run] namespace = {}, run_id = {run_id}", ns.name);
+
+    // "..", for one, would remove the whole namespace
+    if !is_path_component(run_id) {
+        return false;
+    }
+
+    let removed = std::fs::remove_dir_all(format!("{}/{run_id}", ns.artifacts())).is_ok();
+    if removed {
+        EVICTIONS.with_label_values(&["run"]).inc();
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_namespace(name: &str) -> Namespace {
+        let root = std::env::temp_dir().join(format!(
+            "act-local-cache-server-{}-{name}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+
+        let mut ns = Namespace::open("default").unwrap();
+        ns.root = root.to_str().unwrap().to_string();
+        ns
+    }
+
+    fn finalize(ns: &Namespace, run_id: &str, name: Option<&str>) -> StatusCode {
+        let query = FinalizeArtifactQuery {
+            api_version: "6.0-preview".to_string(),
+            name: name.map(|x| x.to_string()),
+        };
+        let input = FinalizeQuery { size: 0 };
+        let res = finalize_artifact(
+            ns.clone(),
+            Compression::None,
+            run_id.to_string(),
+            query,
+            input,
+        );
+        warp::Reply::into_response(res).status()
+    }
+
+    #[test]
+    fn finalizes_only_named_artifact() {
+        let ns = temp_namespace("finalize-named");
+        let dir = format!("{}/1", ns.artifacts());
+        save_file(&format!("{dir}/a/f.txt"), false, 0, b"a");
+        save_file(&format!("{dir}/a[1]/f.txt"), false, 0, b"a[1]");
+        save_file(&format!("{dir}/b/f.txt"), false, 0, b"b");
+
+        // the name is not taken as a glob pattern
+        assert_eq!(finalize(&ns, "1", Some("a[1]")), StatusCode::OK);
+        assert_eq!(list_all_files(&dir), vec!["a[1]/f.txt"]);
+        assert_eq!(count_pending_uploads(&dir), 2);
+
+        assert_eq!(finalize(&ns, "1", Some("a")), StatusCode::OK);
+        assert_eq!(count_pending_uploads(&format!("{dir}/a")), 0);
+        assert_eq!(count_pending_uploads(&format!("{dir}/b")), 1);
+
+        let names: Vec<_> = list_artifacts(&ns, "1")
+            .into_iter()
+            .map(|x| x.name)
+            .collect();
+        assert_eq!(names, vec!["a", "a[1]"]);
+
+        let _ = std::fs::remove_dir_all(&ns.root);
+    }
+
+    #[test]
+    fn finalizes_whole_run_without_name() {
+        let ns = temp_namespace("finalize-run");
+        let dir = format!("{}/1", ns.artifacts());
+        save_file(&format!("{dir}/a/f.txt"), false, 0, b"a");
+        save_file(&format!("{dir}/b/f.txt"), false, 0, b"b");
+
+        assert_eq!(finalize(&ns, "1", None), StatusCode::OK);
+        assert_eq!(count_pending_uploads(&dir), 0);
+        assert_eq!(list_artifacts(&ns, "1").len(), 2);
+
+        assert_eq!(finalize(&ns, "1", Some("../2")), StatusCode::BAD_REQUEST);
+        let _ = std::fs::remove_dir_all(&ns.root);
+    }
+}
@@ -0,0 +1,329 @@
+use crate::artifact::list_runs;
+use crate::cache::list_caches;
+use crate::file::{
+    import_file, is_safe_path, list_all_files, load_metadata, open_stored_file,
+    remove_file_and_metadata, verify_file, FileMetadata,
+};
+use crate::namespace::Namespace;
+use crate::utils::now;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+
+// a bundle carries caches and artifact runs to the store of another server, e.g. to seed a new machine.
+// it is a tar archive of manifest.json followed by the files as they are stored (possibly compressed),
+// so that they are imported without being finalized again.
+const MANIFEST: &str = "manifest.json";
+const FORMAT: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    pub format: u32,
+    pub namespace: String, // exported from
+    pub created_at: u64,
+    pub items: Vec<BundleItem>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum BundleItem {
+    Cache {
+        key: String,
+        version: String,
+        metadata: FileMetadata,
+    },
+    Artifact {
+        #[serde(rename = "runId")]
+        run_id: String,
+        path: String, // <artifact name>/<file>
+        metadata: FileMetadata,
+    },
+}
+
+impl BundleItem {
+    fn archive_path(&self) -> String {
+        match self {
+            BundleItem::Cache { key, version, .. } => format!("caches/{key}/{version}"),
+            BundleItem::Artifact { run_id, path, .. } => format!("artifacts/{run_id}/{path}"),
+        }
+    }
+
+    pub fn metadata(&self) -> &FileMetadata {
+        match self {
+            BundleItem::Cache { metadata, .. } | BundleItem::Artifact { metadata, .. } => metadata,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            BundleItem::Cache { key, version, .. } => format!("cache {key}/{version}"),
+            BundleItem::Artifact { run_id, path, .. } => format!("artifact {run_id}/{path}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BundleFilter {
+    pub prefix: String,          // of cache keys
+    pub version: Option<String>, // of caches
+    pub runs: Vec<String>,       // all runs if empty
+    pub caches: bool,
+    pub artifacts: bool,
+}
+
+pub fn collect_items(ns: &Namespace, filter: &BundleFilter) -> Vec<BundleItem> {
+    let mut items = Vec::new();
+
+    if filter.caches {
+        let mut entries = list_caches(ns, &filter.prefix);
+        entries.sort_by(|x, y| (&x.key, &x.version).cmp(&(&y.key, &y.version)));
+
+        for entry in entries {
+            if filter.version.as_ref().is_none_or(|x| &entry.version == x) {
+                items.push(BundleItem::Cache {
+                    key: entry.key,
+                    version: entry.version,
+                    metadata: entry.metadata,
+                });
+            }
+        }
+    }
+
+    if filter.artifacts {
+        for run_id in list_runs(ns) {
+            if !filter.runs.is_empty() && !filter.runs.contains(&run_id) {
+                continue;
+            }
+
+            let dir = format!("{}/{run_id}", ns.artifacts());
+            let mut files = list_all_files(&dir);
+            files.sort();
+
+            for path in files {
+                if let Some(metadata) = load_metadata(&format!("{dir}/{path}")) {
+                    items.push(BundleItem::Artifact {
+                        run_id: run_id.clone(),
+                        path,
+                        metadata,
+                    });
+                }
+            }
+        }
+    }
+    items
+}
+
+pub fn export_bundle<W: Write>(
+    ns: &Namespace,
+    items: Vec<BundleItem>,
+    output: W,
+) -> std::io::Result<W> {
+    let manifest = Manifest {
+        format: FORMAT,
+        namespace: ns.name.clone(),
+        created_at: now(),
+        items,
+    };
+    let json = serde_json::to_vec_pretty(&manifest).unwrap();
+
+    let mut builder = tar::Builder::new(output);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(manifest.created_at);
+    builder.append_data(&mut header, MANIFEST, json.as_slice())?;
+
+    for item in &manifest.items {
+        let path = format!("{}/{}", ns.root, item.archive_path());
+        let file = open_stored_file(&path)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(file.metadata()?.len());
+        header.set_mode(0o644);
+        header.set_mtime(item.metadata().created_at);
+        builder.append_data(&mut header, item.archive_path(), file)?;
+    }
+
+    builder.into_inner()
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ImportSummary {
+    pub imported: Vec<String>,
+    pub skipped: Vec<String>, // already present
+    pub corrupted: Vec<String>,
+}
+
+fn read_manifest<R: Read>(
+    entry: Option<std::io::Result<tar::Entry<'_, R>>>,
+) -> Result<Manifest, String> {
+    let mut entry = entry.ok_or("empty bundle")?.map_err(|e| e.to_string())?;
+    if entry.path().map_err(|e| e.to_string())? != Path::new(MANIFEST) {
+        return Err(format!(
+            "{MANIFEST} not found at the beginning of the bundle"
+        ));
+    }
+
+    let mut json = Vec::new();
+    entry.read_to_end(&mut json).map_err(|e| e.to_string())?;
+    let manifest: Manifest =
+        serde_json::from_slice(&json).map_err(|e| format!("invalid {MANIFEST}: {e}"))?;
+    if manifest.format != FORMAT {
+        return Err(format!("unsupported bundle format {}", manifest.format));
+    }
+    Ok(manifest)
+}
+
+pub fn import_bundle<R: Read>(
+    ns: Option<Namespace>,
+    input: R,
+    overwrite: bool,
+) -> Result<(Namespace, ImportSummary), String> {
+    // the entries follow the manifest in one pass, as the input may be a pipe
+    let mut archive = tar::Archive::new(input);
+    let mut entries = archive.entries().map_err(|e| e.to_string())?;
+    let manifest = read_manifest(entries.next())?;
+
+    let ns = match ns {
+        Some(ns) => ns,
+        None => Namespace::open(&manifest.namespace)
+            .ok_or_else(|| format!("invalid namespace name: {}", manifest.namespace))?,
+    };
+
+    let mut items = HashMap::new();
+    for item in manifest.items {
+        let path = item.archive_path();
+        if !is_safe_path(&path) {
+            return Err(format!("invalid path in the bundle: {path}"));
+        }
+        items.insert(path, item);
+    }
+
+    let mut summary = ImportSummary::default();
+
+    for entry in entries {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path().map_err(|e| e.to_string())?;
+        let path = path.to_str().unwrap_or_default().to_string();
+        let Some(item) = items.remove(&path) else {
+            return Err(format!("{path} is not in {MANIFEST}"));
+        };
+
+        let dst = format!("{}/{path}", ns.root);
+        if load_metadata(&dst).is_some() && !overwrite {
+            summary.skipped.push(item.describe());
+            continue;
+        }
+
+        import_file(&dst, item.metadata(), &mut entry)
+            .map_err(|e| format!("failed to import {path}: {e}"))?;
+
+        if verify_file(&dst).is_err() {
+            remove_file_and_metadata(&dst);
+            summary.corrupted.push(item.describe());
+        } else {
+            summary.imported.push(item.describe());
+        }
+    }
+
+    if let Some(path) = items.keys().next() {
+        return Err(format!("{path} is missing in the bundle"));
+    }
+    Ok((ns, summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::{dump_file, finalize_files, save_file, Compression};
+
+    fn temp_namespace(name: &str) -> Namespace {
+        let root = std::env::temp_dir().join(format!(
+            "act-local-cache-server-{}-{name}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+
+        let mut ns = Namespace::open("default").unwrap();
+        ns.root = root.to_str().unwrap().to_string();
+        ns
+    }
+
+    fn upload(dir: &str, path: &str, data: &[u8], compression: Compression) {
+        std::fs::create_dir_all(Path::new(&format!("{dir}/{path}")).parent().unwrap()).unwrap();
+        save_file(&format!("{dir}/{path}"), false, 0, data);
+        finalize_files(dir, path, compression);
+    }
+
+    fn export_all(ns: &Namespace) -> Vec<u8> {
+        let filter = BundleFilter {
+            caches: true,
+            artifacts: true,
+            ..Default::default()
+        };
+        export_bundle(ns, collect_items(ns, &filter), Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn export_and_import() {
+        let src = temp_namespace("export-src");
+        upload(&src.caches(), "k1/v1", b"cache contents", Compression::None);
+        upload(&src.caches(), "k2/v2", b"compressed", Compression::Zstd);
+        upload(
+            &src.artifacts(),
+            "1/a/f.txt",
+            b"artifact",
+            Compression::None,
+        );
+        let bundle = export_all(&src);
+
+        let dst = temp_namespace("export-dst");
+        let (_, summary) = import_bundle(Some(dst.clone()), bundle.as_slice(), false).unwrap();
+        assert_eq!(summary.imported.len(), 3);
+        assert!(summary.skipped.is_empty() && summary.corrupted.is_empty());
+
+        let contents = |x: String| dump_file(&x, None, None).unwrap().1;
+        assert_eq!(
+            contents(format!("{}/k1/v1", dst.caches())),
+            b"cache contents"
+        );
+        assert_eq!(contents(format!("{}/k2/v2", dst.caches())), b"compressed");
+        assert_eq!(
+            contents(format!("{}/1/a/f.txt", dst.artifacts())),
+            b"artifact"
+        );
+
+        // present ones are kept unless overwritten
+        let (_, summary) = import_bundle(Some(dst.clone()), bundle.as_slice(), false).unwrap();
+        assert_eq!(summary.skipped.len(), 3);
+        let (_, summary) = import_bundle(Some(dst.clone()), bundle.as_slice(), true).unwrap();
+        assert_eq!(summary.imported.len(), 3);
+
+        let _ = std::fs::remove_dir_all(&src.root);
+        let _ = std::fs::remove_dir_all(&dst.root);
+    }
+
+    #[test]
+    fn import_discards_corrupted_files() {
+        let src = temp_namespace("corrupt-src");
+        upload(
+            &src.caches(),
+            "k/v",
+            b"original contents",
+            Compression::None,
+        );
+        let mut bundle = export_all(&src);
+
+        // the contents follow the manifest and the tar headers
+        let at = bundle.windows(8).position(|x| x == b"original").unwrap();
+        bundle[at] = b'O';
+
+        let dst = temp_namespace("corrupt-dst");
+        let (_, summary) = import_bundle(Some(dst.clone()), bundle.as_slice(), false).unwrap();
+        assert_eq!(summary.corrupted, vec!["cache k/v".to_string()]);
+        assert!(dump_file(&format!("{}/k/v", dst.caches()), None, None).is_err());
+
+        let _ = std::fs::remove_dir_all(&src.root);
+        let _ = std::fs::remove_dir_all(&dst.root);
+    }
+}
@@ -1,8 +1,19 @@
 use crate::file::*;
-use crate::utils::parse_range;
-use log::info;
+use crate::logging::{record_bytes, record_cache_key};
+use crate::metrics::{
+    CACHE_LOOKUPS, DOWNLOADED_BYTES, EVICTIONS, FINALIZE_DURATION, UPLOADED_BYTES,
+};
+use crate::namespace::Namespace;
+use crate::shutdown::{begin_upload, end_upload, is_draining, is_upload_pending, touch_upload};
+use crate::utils::{glob_in, parse_range, status_response};
+use crate::version::{CompressionMethod, Os, VersionComponents};
+use clap::ValueEnum;
+use log::{error, info, warn};
+use once_cell::sync::Lazy;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::Mutex;
 use warp::http::{Response, StatusCode};
 use warp::hyper::body::Bytes;
 use warp::reply::{json, with_status, Json, WithStatus};
@@ -12,9 +23,20 @@ struct StatusResponse {
     status: String,
 }
 
+// what to do with a cache saved again under the same key and version
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverwritePolicy {
+    // rejected with 409, as GitHub; caches are immutable
+    #[default]
+    Deny,
+
+    // replaced, e.g. to iterate on a workflow locally
+    Allow,
+}
+
 // POST http://localhost:8000/_apis/artifactcache/caches
 // -> cacheId
-#[allow(dead_code)]
 #[derive(Deserialize, Clone, Debug)]
 pub struct ReserveCacheQuery {
     key: String,
@@ -22,8 +44,41 @@ pub struct ReserveCacheQuery {
 
     #[serde(rename = "cacheSize")]
     size: usize,
+
+    // how the version was computed; not sent by actions/cache, but by clients that want it recorded
+    paths: Option<Vec<String>>,
+
+    #[serde(rename = "compressionMethod")]
+    compression_method: Option<String>,
+
+    os: Option<String>,
+
+    #[serde(rename = "enableCrossOsArchive")]
+    enable_cross_os_archive: Option<bool>,
+}
+
+impl ReserveCacheQuery {
+    fn version_components(&self) -> Option<VersionComponents> {
+        // unknown values are ignored rather than failing the reservation
+        Some(VersionComponents {
+            paths: self.paths.clone()?,
+            compression_method: match &self.compression_method {
+                Some(x) => Some(CompressionMethod::from_str(x, true).ok()?),
+                None => None,
+            },
+            os: match &self.os {
+                Some(x) => Os::from_str(x, true).ok()?,
+                None => Os::default(),
+            },
+            enable_cross_os_archive: self.enable_cross_os_archive.unwrap_or(false),
+        })
+    }
 }
 
+// version components sent on reservation, by path, until finalized
+static PENDING_COMPONENTS: Lazy<Mutex<HashMap<String, VersionComponents>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
 #[derive(Serialize, Clone, Debug)]
 struct ReserveCacheResponse {
     status: String,
@@ -32,8 +87,118 @@ struct ReserveCacheResponse {
     cache_id: String,
 }
 
-pub fn reserve_cache(query: ReserveCacheQuery) -> Json {
-    info!("[reserve_cache] query = {query:?}");
+#[derive(Serialize, Clone, Debug)]
+struct ErrorResponse {
+    status: String,
+
+    // shown by actions/cache as the reason for not saving the cache
+    message: String,
+}
+
+fn is_valid_cache(key: &str, version: &str) -> bool {
+    // each a directory of its own; "..", for one, would reach other namespaces
+    is_path_component(key) && is_path_component(version)
+}
+
+fn invalid_cache(function: &str) -> WithStatus<Json> {
+    let res = StatusResponse {
+        status: "invalid key or version".to_string(),
+    };
+    info!("[{function}] response = {res:?}");
+
+    with_status(json(&res), StatusCode::BAD_REQUEST)
+}
+
+pub fn reserve_cache(
+    ns: Namespace,
+    quota: Option<usize>,
+    max_size: usize,
+    overwrite: OverwritePolicy,
+    query: ReserveCacheQuery,
+) -> WithStatus<Json> {
+    info!("[reserve_cache] namespace = {}, query = {query:?}", ns.name);
+    record_cache_key(&query.key);
+
+    if !is_valid_cache(&query.key, &query.version) {
+        return invalid_cache("reserve_cache");
+    }
+
+    if is_draining() {
+        let res = StatusResponse {
+            status: "server is shutting down".to_string(),
+        };
+        info!("[reserve_cache] response = {res:?}");
+
+        return with_status(json(&res), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    let path = format!("{}/{}/{}", ns.caches(), query.key, query.version);
+    if overwrite == OverwritePolicy::Deny && load_metadata(&path).is_some() {
+        let res = already_exists(&query.key, &query.version);
+        info!("[reserve_cache] response = {res:?}");
+
+        return with_status(json(&res), StatusCode::CONFLICT);
+    }
+
+    // chunks of two uploads under the same key and version would be mixed, whatever the policy
+    let id = format!("cache:{}/{}/{}", ns.name, query.key, query.version);
+    if is_upload_pending(&id) {
+        let res = ErrorResponse {
+            status: "already reserved".to_string(),
+            message: format!(
+                "Unable to reserve cache with key {}, another job may be creating this cache.",
+                query.key
+            ),
+        };
+        info!("[reserve_cache] response = {res:?}");
+
+        return with_status(json(&res), StatusCode::CONFLICT);
+    }
+
+    let size = query.size;
+    let size_mb = (size as f64 / (1024.0 * 1024.0)).round();
+    if size > max_size {
+        let res = ErrorResponse {
+            status: "cache size exceeded".to_string(),
+            message: format!(
+                "Cache size of ~{size_mb} MB ({size} B) is over the {max_size} B limit, not saving cache."
+            ),
+        };
+        info!("[reserve_cache] response = {res:?}");
+
+        return with_status(json(&res), StatusCode::BAD_REQUEST);
+    }
+
+    if let Some(quota) = quota {
+        let (_, usage) = disk_usage(&ns.caches());
+        if usage + size > quota {
+            let res = ErrorResponse {
+                status: "namespace quota exceeded".to_string(),
+                message: format!(
+                    "Cache size of ~{size_mb} MB ({size} B) is over the {quota} B quota of namespace {} ({usage} B used), not saving cache.",
+                    ns.name
+                ),
+            };
+            info!("[reserve_cache] response = {res:?}");
+
+            return with_status(json(&res), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    if let Some(components) = query.version_components() {
+        // recorded only if they reproduce the version, so that the metadata never lies
+        let version = components.version();
+        if version == query.version {
+            PENDING_COMPONENTS.lock().unwrap().insert(path, components);
+        } else {
+            warn!(
+                "[reserve_cache] version {} does not match {components:?} (computed = {version})",
+                query.version
+            );
+        }
+    }
+
+    begin_upload(&id);
 
     let res = ReserveCacheResponse {
         status: "success".to_string(),
@@ -41,37 +206,48 @@ pub fn reserve_cache(query: ReserveCacheQuery) -> Json {
     };
     info!("[reserve_cache] response = {res:?}");
 
-    json(&res)
+    with_status(json(&res), StatusCode::OK)
 }
 
 pub fn upload_cache(
+    ns: Namespace,
     key: String,
     version: String,
     encoding: Option<String>,
     range: Option<String>,
     input: Bytes,
-) -> Json {
+) -> WithStatus<Json> {
     info!(
         "[upload_cache] version = {version}, key = {key}, encoding = {encoding:?}, range = {range:?}, input = <{} bytes>",
         input.len()
     );
+    record_cache_key(&key);
+    record_bytes(input.len());
+
+    if !is_valid_cache(&key, &version) {
+        return invalid_cache("upload_cache");
+    }
 
     // format chunk prefix that can be safely sorted into the original chunk order
     // (this assumes total bytes being less than 1TB)
-    let path = format!(".act_local_cache/caches/{key}/{version}");
+    let path = format!("{}/{key}/{version}", ns.caches());
 
     // workaround for gzipped stream
     let is_gzip = encoding.as_deref() == Some("gzip");
 
     let range = range.as_deref().map_or(0..input.len(), parse_range);
     save_file(&path, is_gzip, range.start, &input.slice(..));
+    touch_upload(&format!("cache:{}/{key}/{version}", ns.name));
+    UPLOADED_BYTES
+        .with_label_values(&["upload_cache"])
+        .inc_by(input.len() as u64);
 
     let res = StatusResponse {
         status: "success".to_string(),
     };
     info!("[upload_cache] response = {res:?}");
 
-    json(&res)
+    with_status(json(&res), StatusCode::OK)
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -79,10 +255,59 @@ pub struct FinalizeQuery {
     size: usize,
 }
 
-pub fn finalize_cache(key: String, version: String, input: FinalizeQuery) -> WithStatus<Json> {
+fn already_exists(key: &str, version: &str) -> ErrorResponse {
+    ErrorResponse {
+        status: "already exists".to_string(),
+        message: format!(
+            "Cache with key {key} and version {version} already exists; caches are immutable."
+        ),
+    }
+}
+
+pub fn finalize_cache(
+    ns: Namespace,
+    compression: Compression,
+    overwrite: OverwritePolicy,
+    key: String,
+    version: String,
+    input: FinalizeQuery,
+) -> WithStatus<Json> {
     info!("[finalize_cache] version = {version}, key = {key}, input = {input:?}");
+    record_cache_key(&key);
+
+    if !is_valid_cache(&key, &version) {
+        return invalid_cache("finalize_cache");
+    }
+
+    // uploaded without reservation, or saved by another job in the meantime; the chunks are discarded
+    let path = format!("{}/{key}/{version}", ns.caches());
+    if overwrite == OverwritePolicy::Deny && load_metadata(&path).is_some() {
+        let chunks = glob_in(&ns.caches(), &format!("{key}/{version}.uploadTemporary.*"));
+        for chunk in chunks.unwrap_or_default() {
+            let _ = std::fs::remove_file(format!("{}/{}", ns.caches(), chunk.to_str().unwrap()));
+        }
+        end_upload(&format!("cache:{}/{key}/{version}", ns.name));
+        PENDING_COMPONENTS.lock().unwrap().remove(&path);
+
+        let res = already_exists(&key, &version);
+        info!("[finalize_cache] response = {res:?}");
+
+        return with_status(json(&res), StatusCode::CONFLICT);
+    }
+
+    let timer = FINALIZE_DURATION
+        .with_label_values(&["cache"])
+        .start_timer();
+    let size = finalize_files(&ns.caches(), &format!("{key}/{version}*"), compression);
+    timer.observe_duration();
+    end_upload(&format!("cache:{}/{key}/{version}", ns.name));
+
+    let components = PENDING_COMPONENTS.lock().unwrap().remove(&path);
+    if let (Some(components), Some(mut metadata)) = (components, load_metadata(&path)) {
+        metadata.version_components = Some(components);
+        save_metadata(&path, &metadata);
+    }
 
-    let size = finalize_files(".act_local_cache/caches", &format!("{key}/{version}*"));
     if size != input.size {
         let expected = input.size;
         info!("[finalize_cache] upload size differs (expected = {expected}, actual = {size})");
@@ -99,8 +324,8 @@ pub fn finalize_cache(key: String, version: String, input: FinalizeQuery) -> Wit
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct EnumerateQuery {
-    keys: String,
-    version: String,
+    pub keys: String,
+    pub version: String,
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -114,56 +339,357 @@ struct UrlResponse {
     key: String,
 }
 
-pub fn enumerate_caches(host: &str, query: EnumerateQuery) -> WithStatus<Json> {
-    info!("[enumerate_caches] query = {query:?}");
+impl UrlResponse {
+    fn new(ns: &Namespace, key: &str, version: &str) -> UrlResponse {
+        let url = format!(
+            "{}/_apis/artifactcache/cache/download/{key}/{version}",
+            ns.host
+        );
+        UrlResponse {
+            status: "success".to_string(),
+            url,
+            key: key.to_string(),
+        }
+    }
+}
+
+pub fn cache_found(ns: &Namespace, key: &str, version: &str) -> WithStatus<Json> {
+    // the response of enumerate_caches for a cache found elsewhere, to be downloaded from this server
+    let res = UrlResponse::new(ns, key, version);
+    info!("[cache_found] response = {res:?}");
+
+    with_status(json(&res), StatusCode::OK)
+}
+
+pub fn enumerate_caches(
+    ns: Namespace,
+    compatible: bool,
+    query: EnumerateQuery,
+) -> WithStatus<Json> {
+    info!(
+        "[enumerate_caches] namespace = {}, query = {query:?}",
+        ns.name
+    );
+    record_cache_key(&query.keys);
 
     let version = query.version;
+    if !query
+        .keys
+        .split(',')
+        .all(|key| is_valid_cache(key, &version))
+    {
+        return invalid_cache("enumerate_caches");
+    }
 
     let mut array = Vec::new();
     for key in query.keys.split(',') {
-        let path = format!(".act_local_cache/caches/{key}/{version}");
+        let path = format!("{}/{key}/{version}", ns.caches());
 
         if Path::new(&path).exists() {
-            let url = format!("{host}/_apis/artifactcache/cache/download/{key}/{version}");
-            array.push(UrlResponse {
-                status: "success".to_string(),
-                url,
-                key: key.to_string(),
-            });
+            array.push(UrlResponse::new(&ns, key, &version));
         }
     }
 
     if let Some(res) = array.pop() {
+        CACHE_LOOKUPS.with_label_values(&["hit"]).inc();
         info!("[enumerate_caches] response = {res:?}");
-        with_status(json(&res), StatusCode::OK)
-    } else {
-        let res = StatusResponse {
-            status: "not found".to_string(),
-        };
-        info!("[enumerate_caches] response = {res:?}");
+        return with_status(json(&res), StatusCode::OK);
+    }
 
-        with_status(json(&res), StatusCode::NOT_FOUND)
+    // another version of the same key that the client can restore, e.g. saved with zstd-without-long
+    let compatible = compatible
+        .then(|| {
+            query
+                .keys
+                .split(',')
+                .find_map(|key| find_compatible_cache(&ns, key, &version))
+        })
+        .flatten();
+    if let Some(entry) = compatible {
+        CACHE_LOOKUPS.with_label_values(&["compatible"]).inc();
+        let res = UrlResponse::new(&ns, &entry.key, &entry.version);
+        info!("[enumerate_caches] response = {res:?} (compatible with {version})");
+        return with_status(json(&res), StatusCode::OK);
     }
+
+    CACHE_LOOKUPS.with_label_values(&["miss"]).inc();
+    let res = StatusResponse {
+        status: "not found".to_string(),
+    };
+    info!("[enumerate_caches] response = {res:?}");
+
+    with_status(json(&res), StatusCode::NOT_FOUND)
 }
 
-pub fn download_cache(key: String, version: String, range: Option<String>) -> Response<Vec<u8>> {
-    info!("[download_cache] version = {version}, key = {key}, range = {range:?}");
+fn find_compatible_cache(ns: &Namespace, key: &str, version: &str) -> Option<CacheEntry> {
+    // only caches with version components recorded can be told compatible
+    let mut entries: Vec<_> = list_caches(ns, key)
+        .into_iter()
+        .filter(|x| x.key == key)
+        .filter(|x| {
+            let components = x.metadata.version_components.as_ref();
+            components.is_some_and(|c| c.compatible_versions().iter().any(|v| v == version))
+        })
+        .collect();
+
+    // the newest one
+    entries.sort_by_key(|x| x.metadata.created_at);
+    entries.pop()
+}
 
-    let (is_gzip, data) = dump_file(
-        &format!(".act_local_cache/caches/{key}/{version}"),
-        range.as_deref().map(parse_range),
-    );
+pub fn download_cache(
+    ns: Namespace,
+    key: String,
+    version: String,
+    range: Option<String>,
+    accept_encoding: Option<String>,
+) -> Response<Vec<u8>> {
+    info!("[download_cache] version = {version}, key = {key}, range = {range:?}, accept_encoding = {accept_encoding:?}");
+    record_cache_key(&key);
+
+    if !is_valid_cache(&key, &version) {
+        info!("[download_cache] response = <invalid key or version>");
+        return status_response(StatusCode::BAD_REQUEST, "invalid key or version");
+    }
 
-    // workaround for gzipped stream
+    let path = format!("{}/{key}/{version}", ns.caches());
+    let Some(metadata) = load_metadata(&path) else {
+        info!("[download_cache] response = <not found>");
+        return status_response(StatusCode::NOT_FOUND, "not found");
+    };
+
+    let range = range.as_deref().map(parse_range);
+    let (encoding, data) = match dump_file(&path, range, accept_encoding.as_deref()) {
+        Ok(x) => x,
+        Err(e) => {
+            error!("[download_cache] {path}: {e}");
+            return status_response(StatusCode::INTERNAL_SERVER_ERROR, "corrupted");
+        }
+    };
+    touch_metadata(&path);
+
+    // of the whole file as uploaded
     let header = Response::builder().header("Content-Type", "application/octet-stream");
-    let header = if is_gzip {
-        header.header("Content-Encoding", "gzip")
+    let header = match metadata.sha256 {
+        Some(sha256) => header.header("X-Checksum-Sha256", sha256),
+        None => header,
+    };
+    let header = if let Some(encoding) = encoding {
+        header.header("Content-Encoding", encoding)
     } else {
         header
     };
 
     let len = data.len();
+    record_bytes(len);
+    DOWNLOADED_BYTES
+        .with_label_values(&["download_cache"])
+        .inc_by(len as u64);
     info!("[download_cache] response = <{len} bytes>");
 
     header.body(data).unwrap()
 }
+
+#[derive(Clone, Debug)]
+pub struct CacheEntry {
+    pub key: String,
+    pub version: String,
+    pub path: String,
+    pub metadata: FileMetadata,
+}
+
+pub fn list_caches(ns: &Namespace, prefix: &str) -> Vec<CacheEntry> {
+    // caches are saved at {key}/{version}; keys may contain slashes
+    let dir = ns.caches();
+    let paths = glob_in(&dir, "**/*").unwrap_or_default();
+
+    let mut array = Vec::new();
+    for path in &paths {
+        let path = path.to_str().unwrap();
+        if is_metadata(path) || is_temporary(path) {
+            continue;
+        }
+
+        let Some((key, version)) = path.rsplit_once('/') else {
+            continue;
+        };
+        if !key.starts_with(prefix) {
+            continue;
+        }
+
+        let path = format!("{dir}/{path}");
+        if Path::new(&path).is_dir() {
+            continue;
+        }
+
+        if let Some(metadata) = load_metadata(&path) {
+            array.push(CacheEntry {
+                key: key.to_string(),
+                version: version.to_string(),
+                path,
+                metadata,
+            });
+        }
+    }
+    array
+}
+
+pub fn remove_cache(ns: &Namespace, entry: &CacheEntry) {
+    info!(
+        "[remove_cache] namespace = {}, key = {}, version = {}",
+        ns.name, entry.key, entry.version
+    );
+
+    // also discard chunks left by an unfinished upload
+    let chunks = glob_in(&ns.caches(), &format!("{}/{}.*", entry.key, entry.version));
+    for chunk in chunks.unwrap_or_default() {
+        let _ = std::fs::remove_file(format!("{}/{}", ns.caches(), chunk.to_str().unwrap()));
+    }
+
+    remove_file_and_metadata(&entry.path);
+    remove_empty_dirs(&ns.caches(), &entry.path);
+    EVICTIONS.with_label_values(&["cache"]).inc();
+}
+
+pub fn cache_id(key: &str, version: &str) -> u64 {
+    // stable numeric id for the GitHub-compatible API; FNV-1a, truncated so that it stays exact in JSON numbers
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in format!("{key}/{version}").bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash & ((1 << 53) - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::Reply;
+
+    fn temp_namespace(name: &str) -> Namespace {
+        let root = std::env::temp_dir().join(format!(
+            "act-local-cache-server-{}-{name}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+
+        // uploads in progress are tracked by namespace name, across the tests of the process
+        let mut ns = Namespace::open("default").unwrap();
+        ns.name = name.to_string();
+        ns.root = root.to_str().unwrap().to_string();
+        ns
+    }
+
+    fn reserve(ns: &Namespace, key: &str, overwrite: OverwritePolicy) -> StatusCode {
+        let query = ReserveCacheQuery {
+            key: key.to_string(),
+            version: "v1".to_string(),
+            size: 5,
+            paths: None,
+            compression_method: None,
+            os: None,
+            enable_cross_os_archive: None,
+        };
+        reserve_cache(ns.clone(), None, usize::MAX, overwrite, query)
+            .into_response()
+            .status()
+    }
+
+    fn upload_and_finalize(ns: &Namespace, key: &str, overwrite: OverwritePolicy) -> StatusCode {
+        let data = Bytes::from_static(b"hello");
+        upload_cache(
+            ns.clone(),
+            key.to_string(),
+            "v1".to_string(),
+            None,
+            None,
+            data,
+        );
+
+        let input = FinalizeQuery { size: 5 };
+        finalize_cache(
+            ns.clone(),
+            Compression::None,
+            overwrite,
+            key.to_string(),
+            "v1".to_string(),
+            input,
+        )
+        .into_response()
+        .status()
+    }
+
+    #[test]
+    fn rejects_keys_outside_namespace() {
+        let ns = temp_namespace("reserve-invalid");
+        assert_eq!(
+            reserve(&ns, "..", OverwritePolicy::Deny),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            reserve(&ns, "a/b", OverwritePolicy::Deny),
+            StatusCode::BAD_REQUEST
+        );
+
+        let query = EnumerateQuery {
+            keys: "k1,../namespaces/team/caches/k1".to_string(),
+            version: "v1".to_string(),
+        };
+        let res = enumerate_caches(ns.clone(), false, query).into_response();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = download_cache(ns, "..".to_string(), "v1".to_string(), None, None);
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn rejects_saving_existing_cache() {
+        let ns = temp_namespace("reserve-existing");
+        assert_eq!(reserve(&ns, "k1", OverwritePolicy::Deny), StatusCode::OK);
+        assert_eq!(
+            upload_and_finalize(&ns, "k1", OverwritePolicy::Deny),
+            StatusCode::OK
+        );
+        assert_eq!(
+            reserve(&ns, "k1", OverwritePolicy::Deny),
+            StatusCode::CONFLICT
+        );
+
+        // chunks uploaded without a reservation are discarded
+        assert_eq!(
+            upload_and_finalize(&ns, "k1", OverwritePolicy::Deny),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(count_pending_uploads(&ns.caches()), 0);
+
+        assert_eq!(reserve(&ns, "k1", OverwritePolicy::Allow), StatusCode::OK);
+        assert_eq!(
+            upload_and_finalize(&ns, "k1", OverwritePolicy::Allow),
+            StatusCode::OK
+        );
+        let _ = std::fs::remove_dir_all(&ns.root);
+    }
+
+    #[test]
+    fn rejects_reserving_cache_being_uploaded() {
+        let ns = temp_namespace("reserve-pending");
+        assert_eq!(reserve(&ns, "k1", OverwritePolicy::Deny), StatusCode::OK);
+        assert_eq!(
+            reserve(&ns, "k1", OverwritePolicy::Deny),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            reserve(&ns, "k1", OverwritePolicy::Allow),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(reserve(&ns, "k2", OverwritePolicy::Deny), StatusCode::OK);
+
+        // reserved again once finalized, if allowed
+        assert_eq!(
+            upload_and_finalize(&ns, "k1", OverwritePolicy::Deny),
+            StatusCode::OK
+        );
+        assert_eq!(reserve(&ns, "k1", OverwritePolicy::Allow), StatusCode::OK);
+        let _ = std::fs::remove_dir_all(&ns.root);
+    }
+}
@@ -0,0 +1,707 @@
+use crate::artifact::{find_artifact, list_artifacts, list_runs, remove_run, write_artifact_zip};
+use crate::bundle::{collect_items, export_bundle, import_bundle, BundleFilter};
+use crate::cache::{list_caches, remove_cache};
+use crate::config::{Config, ServeArgs};
+use crate::file::{
+    import_file, is_path_component, last_upload_activity, list_all_files, load_metadata,
+    move_file_and_metadata, remove_empty_dirs, remove_stale_chunks, verify_file, FileMetadata,
+};
+use crate::namespace::{list_namespaces, Namespace, DATA_DIR, DEFAULT_NAMESPACE};
+use crate::utils::{format_time, now};
+use crate::version::{CompressionMethod, Os, VersionComponents};
+use clap::Subcommand;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::time::Duration;
+
+// maintenance commands; they operate directly on the data directory, without the server running
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+    #[command(about = "Run the server (default)")]
+    Serve(ServeArgs),
+
+    #[command(subcommand, about = "List and remove caches")]
+    Cache(CacheCommand),
+
+    #[command(subcommand, about = "Browse and download artifacts")]
+    Artifacts(ArtifactsCommand),
+
+    #[command(about = "Remove caches and artifact runs not used for a while")]
+    Prune {
+        #[clap(long, help = "Age to remove, e.g. \"7d\" or \"12h\"", value_parser = humantime::parse_duration)]
+        older_than: Duration,
+
+        #[clap(short, long, help = "Namespace [default: all namespaces]")]
+        namespace: Option<String>,
+
+        #[clap(long, help = "Only print what would be removed")]
+        dry_run: bool,
+    },
+
+    #[command(about = "Show the number and size of caches and artifacts per namespace")]
+    Stats,
+
+    #[command(about = "Check stored caches and artifacts against their checksums")]
+    Verify {
+        #[clap(short, long, help = "Namespace [default: all namespaces]")]
+        namespace: Option<String>,
+
+        #[clap(
+            long,
+            help = "Move corrupted files to .act_local_cache/quarantine/<namespace>/"
+        )]
+        quarantine: bool,
+    },
+
+    #[command(about = "Write caches and artifact runs to a bundle (tar) to import elsewhere")]
+    Export {
+        #[clap(help = "Output file")]
+        output: String,
+
+        #[clap(long, help = "Key prefix of caches [default: all caches]")]
+        prefix: Option<String>,
+
+        #[clap(long, help = "Only caches of this version")]
+        version: Option<String>,
+
+        #[clap(
+            long = "run",
+            help = "Run ID of artifacts, can be repeated [default: all runs]"
+        )]
+        runs: Vec<String>,
+
+        #[clap(long, help = "Export caches only", conflicts_with = "artifacts_only")]
+        caches_only: bool,
+
+        #[clap(long, help = "Export artifacts only")]
+        artifacts_only: bool,
+
+        #[clap(short, long, help = "Namespace", default_value = DEFAULT_NAMESPACE)]
+        namespace: String,
+    },
+
+    #[command(about = "Import caches and artifact runs from a bundle")]
+    Import {
+        #[clap(help = "Input file")]
+        input: String,
+
+        #[clap(short, long, help = "Namespace [default: the namespace exported from]")]
+        namespace: Option<String>,
+
+        #[clap(long, help = "Replace caches and artifacts already present")]
+        overwrite: bool,
+    },
+
+    #[command(
+        about = "Register a cache archive downloaded from GitHub (e.g. cache.tzst) under its key"
+    )]
+    Ingest {
+        #[clap(help = "Archive file")]
+        file: String,
+
+        #[clap(long, help = "Cache key")]
+        key: String,
+
+        #[clap(
+            long = "path",
+            help = "Path given to actions/cache, can be repeated",
+            required_unless_present = "version"
+        )]
+        paths: Vec<String>,
+
+        #[clap(
+            long,
+            value_enum,
+            help = "Compression method of the archive [default: by the file name]"
+        )]
+        compression_method: Option<CompressionMethod>,
+
+        #[clap(
+            long,
+            value_enum,
+            help = "OS of the runner that saved the cache",
+            default_value = "linux"
+        )]
+        os: Os,
+
+        #[clap(long, help = "The cache was saved with enableCrossOsArchive")]
+        enable_cross_os_archive: bool,
+
+        #[clap(
+            long,
+            help = "Version to register the cache under, instead of computing it",
+            conflicts_with = "paths"
+        )]
+        version: Option<String>,
+
+        #[clap(short, long, help = "Namespace", default_value = DEFAULT_NAMESPACE)]
+        namespace: String,
+
+        #[clap(long, help = "Replace the cache if already present")]
+        overwrite: bool,
+    },
+
+    #[command(subcommand, about = "Inspect the configuration")]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum ConfigCommand {
+    #[command(about = "Validate the configuration and print the effective one")]
+    Check(ServeArgs),
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum CacheCommand {
+    #[command(about = "List caches")]
+    Ls {
+        #[clap(help = "Key prefix")]
+        prefix: Option<String>,
+
+        #[clap(short, long, help = "Namespace", default_value = DEFAULT_NAMESPACE)]
+        namespace: String,
+    },
+
+    #[command(about = "Compute the version of a cache as actions/cache does")]
+    Version {
+        #[clap(
+            long = "path",
+            help = "Path given to actions/cache, can be repeated",
+            required = true
+        )]
+        paths: Vec<String>,
+
+        #[clap(long, value_enum, help = "Compression method [default: show all]")]
+        compression_method: Option<CompressionMethod>,
+
+        #[clap(long, value_enum, help = "OS of the runner", default_value = "linux")]
+        os: Os,
+
+        #[clap(long, help = "enableCrossOsArchive of actions/cache")]
+        enable_cross_os_archive: bool,
+    },
+
+    #[command(about = "Remove a cache")]
+    Rm {
+        #[clap(help = "Key")]
+        key: String,
+
+        #[clap(long, help = "Remove only this version")]
+        version: Option<String>,
+
+        #[clap(short, long, help = "Namespace", default_value = DEFAULT_NAMESPACE)]
+        namespace: String,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum ArtifactsCommand {
+    #[command(about = "List runs, or artifacts of a run")]
+    Ls {
+        #[clap(help = "Run ID")]
+        run_id: Option<String>,
+
+        #[clap(short, long, help = "Namespace", default_value = DEFAULT_NAMESPACE)]
+        namespace: String,
+    },
+
+    #[command(about = "Save an artifact as a zip archive")]
+    Download {
+        #[clap(help = "Run ID")]
+        run_id: String,
+
+        #[clap(help = "Artifact name")]
+        name: String,
+
+        #[clap(short, long, help = "Output file [default: <name>.zip]")]
+        output: Option<String>,
+
+        #[clap(short, long, help = "Namespace", default_value = DEFAULT_NAMESPACE)]
+        namespace: String,
+    },
+}
+
+pub fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Serve(_) => unreachable!(),
+        Command::Cache(CacheCommand::Ls { prefix, namespace }) => {
+            let ns = open_namespace(&namespace)?;
+            list_all_caches(&ns, &prefix.unwrap_or_default())
+        }
+        Command::Cache(CacheCommand::Rm {
+            key,
+            version,
+            namespace,
+        }) => {
+            let ns = open_namespace(&namespace)?;
+            remove_caches(&ns, &key, version.as_deref())
+        }
+        Command::Cache(CacheCommand::Version {
+            paths,
+            compression_method,
+            os,
+            enable_cross_os_archive,
+        }) => {
+            let components = VersionComponents {
+                paths: split_paths(&paths),
+                compression_method,
+                os,
+                enable_cross_os_archive,
+            };
+            for (method, version) in components.versions() {
+                println!("{:<18} {version}", method.as_str());
+            }
+            Ok(())
+        }
+        Command::Artifacts(ArtifactsCommand::Ls { run_id, namespace }) => {
+            let ns = open_namespace(&namespace)?;
+            if let Some(run_id) = run_id {
+                list_artifacts_of_run(&ns, &run_id)
+            } else {
+                list_all_runs(&ns)
+            }
+        }
+        Command::Artifacts(ArtifactsCommand::Download {
+            run_id,
+            name,
+            output,
+            namespace,
+        }) => {
+            let ns = open_namespace(&namespace)?;
+            download_artifact(&ns, &run_id, &name, output)
+        }
+        Command::Prune {
+            older_than,
+            namespace,
+            dry_run,
+        } => {
+            let namespaces = if let Some(namespace) = namespace {
+                vec![open_namespace(&namespace)?]
+            } else {
+                list_namespaces()
+            };
+            let before = now().saturating_sub(older_than.as_secs());
+
+            for ns in &namespaces {
+                prune(ns, before, dry_run);
+            }
+            Ok(())
+        }
+        Command::Stats => show_stats(),
+        Command::Verify {
+            namespace,
+            quarantine,
+        } => {
+            let namespaces = if let Some(namespace) = namespace {
+                vec![open_namespace(&namespace)?]
+            } else {
+                list_namespaces()
+            };
+
+            let mut corrupted = 0;
+            for ns in &namespaces {
+                corrupted += verify(ns, quarantine);
+            }
+            if corrupted > 0 {
+                return Err(format!("{corrupted} corrupted files found"));
+            }
+            Ok(())
+        }
+        Command::Export {
+            output,
+            prefix,
+            version,
+            runs,
+            caches_only,
+            artifacts_only,
+            namespace,
+        } => {
+            let ns = open_namespace(&namespace)?;
+            let filter = BundleFilter {
+                prefix: prefix.unwrap_or_default(),
+                version,
+                runs,
+                caches: !artifacts_only,
+                artifacts: !caches_only,
+            };
+            export(&ns, &filter, &output)
+        }
+        Command::Import {
+            input,
+            namespace,
+            overwrite,
+        } => {
+            let ns = namespace.as_deref().map(open_namespace).transpose()?;
+            import(ns, &input, overwrite)
+        }
+        Command::Ingest {
+            file,
+            key,
+            paths,
+            compression_method,
+            os,
+            enable_cross_os_archive,
+            version,
+            namespace,
+            overwrite,
+        } => {
+            let ns = open_namespace(&namespace)?;
+            let components = match version {
+                Some(_) => None,
+                None => {
+                    let method = compression_method
+                        .or_else(|| CompressionMethod::from_file_name(&file))
+                        .ok_or_else(|| {
+                            format!(
+                                "compression method of {file} unknown; give --compression-method"
+                            )
+                        })?;
+                    Some(VersionComponents {
+                        paths: split_paths(&paths),
+                        compression_method: Some(method),
+                        os,
+                        enable_cross_os_archive,
+                    })
+                }
+            };
+            ingest(&ns, &file, &key, version, components, overwrite)
+        }
+        Command::Config(ConfigCommand::Check(args)) => {
+            let config = Config::load(&args)?;
+            print!("{}", config.to_masked_toml());
+            Ok(())
+        }
+    }
+}
+
+fn open_namespace(name: &str) -> Result<Namespace, String> {
+    Namespace::open(name).ok_or_else(|| format!("invalid namespace name: {name}"))
+}
+
+fn list_all_caches(ns: &Namespace, prefix: &str) -> Result<(), String> {
+    let mut entries = list_caches(ns, prefix);
+    entries.sort_by(|x, y| (&x.key, &x.version).cmp(&(&y.key, &y.version)));
+
+    println!(
+        "{:<40} {:<16} {:>14} {:<20} {:<20}",
+        "KEY", "VERSION", "SIZE", "CREATED", "ACCESSED"
+    );
+    for entry in &entries {
+        println!(
+            "{:<40} {:<16} {:>14} {:<20} {:<20}",
+            entry.key,
+            &entry.version[..entry.version.len().min(16)],
+            entry.metadata.size,
+            format_time(entry.metadata.created_at),
+            format_time(entry.metadata.last_accessed_at)
+        );
+    }
+    Ok(())
+}
+
+fn remove_caches(ns: &Namespace, key: &str, version: Option<&str>) -> Result<(), String> {
+    let entries: Vec<_> = list_caches(ns, key)
+        .into_iter()
+        .filter(|x| x.key == key && version.is_none_or(|v| x.version == v))
+        .collect();
+    if entries.is_empty() {
+        return Err(format!("cache {key} not found"));
+    }
+
+    for entry in &entries {
+        remove_cache(ns, entry);
+        println!("removed {}/{}", entry.key, entry.version);
+    }
+    Ok(())
+}
+
+fn prune(ns: &Namespace, before: u64, dry_run: bool) {
+    let verb = if dry_run { "would remove" } else { "removed" };
+
+    for entry in list_caches(ns, "") {
+        if entry.metadata.last_accessed_at < before {
+            if !dry_run {
+                remove_cache(ns, &entry);
+            }
+            println!("{verb} cache {}/{} ({})", entry.key, entry.version, ns.name);
+        }
+    }
+
+    for run_id in list_runs(ns) {
+        // a run is removed when all of its artifacts are old enough, and no upload is in progress;
+        // chunks of uploads abandoned long ago are removed with the run or by remove_stale_chunks
+        let artifacts = list_artifacts(ns, &run_id);
+        let uploaded = last_upload_activity(&format!("{}/{run_id}", ns.artifacts()));
+        let newest = artifacts
+            .iter()
+            .map(|x| x.created_at)
+            .chain(uploaded)
+            .max()
+            .unwrap_or(0);
+
+        if newest < before {
+            if !dry_run {
+                remove_run(ns, &run_id);
+            }
+            println!("{verb} artifacts of run {run_id} ({})", ns.name);
+        }
+    }
+
+    if !dry_run {
+        let count = remove_stale_chunks(&ns.caches(), before)
+            + remove_stale_chunks(&ns.artifacts(), before);
+        if count > 0 {
+            println!("{verb} {count} chunks of unfinished uploads ({})", ns.name);
+        }
+    }
+}
+
+fn verify(ns: &Namespace, quarantine: bool) -> usize {
+    // -> number of corrupted files
+    let mut counts = [0; 3]; // ok, unverified (no checksum recorded), corrupted
+
+    for (kind, dir) in [("caches", ns.caches()), ("artifacts", ns.artifacts())] {
+        for file in list_all_files(&dir) {
+            let path = format!("{dir}/{file}");
+            match verify_file(&path) {
+                Ok(Some(_)) => counts[0] += 1,
+                Ok(None) => counts[1] += 1,
+                Err(e) => {
+                    counts[2] += 1;
+                    println!("corrupted {kind} {file} ({}): {e}", ns.name);
+
+                    if quarantine {
+                        let dst = format!("{DATA_DIR}/quarantine/{}/{kind}/{file}", ns.name);
+                        match move_file_and_metadata(&path, &dst) {
+                            Ok(()) => {
+                                remove_empty_dirs(&dir, &path);
+                                println!("moved to {dst}");
+                            }
+                            Err(e) => println!("failed to move to {dst}: {e}"),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let [ok, unverified, corrupted] = counts;
+    println!(
+        "{}: {ok} ok, {unverified} without checksum, {corrupted} corrupted",
+        ns.name
+    );
+    corrupted
+}
+
+fn export(ns: &Namespace, filter: &BundleFilter, output: &str) -> Result<(), String> {
+    let items = collect_items(ns, filter);
+    if items.is_empty() {
+        return Err("nothing to export".to_string());
+    }
+    let count = items.len();
+    let size: usize = items.iter().map(|x| x.metadata().size).sum();
+
+    let file =
+        std::fs::File::create(output).map_err(|e| format!("failed to create {output}: {e}"))?;
+    let file = export_bundle(ns, items, std::io::BufWriter::new(file))
+        .map_err(|e| format!("failed to write {output}: {e}"))?;
+    file.into_inner()
+        .map_err(|e| format!("failed to write {output}: {e}"))?
+        .sync_all()
+        .map_err(|e| format!("failed to write {output}: {e}"))?;
+
+    println!(
+        "exported {count} files ({size} bytes) of {} to {output}",
+        ns.name
+    );
+    Ok(())
+}
+
+fn import(ns: Option<Namespace>, input: &str, overwrite: bool) -> Result<(), String> {
+    let file = std::fs::File::open(input).map_err(|e| format!("failed to open {input}: {e}"))?;
+    let (ns, summary) = import_bundle(ns, std::io::BufReader::new(file), overwrite)
+        .map_err(|e| format!("failed to import {input}: {e}"))?;
+
+    for x in &summary.imported {
+        println!("imported {x} ({})", ns.name);
+    }
+    for x in &summary.skipped {
+        println!("skipped {x} ({}): already present", ns.name);
+    }
+    for x in &summary.corrupted {
+        println!("discarded {x} ({}): checksum mismatch", ns.name);
+    }
+    println!(
+        "{}: {} imported, {} skipped, {} corrupted",
+        ns.name,
+        summary.imported.len(),
+        summary.skipped.len(),
+        summary.corrupted.len()
+    );
+
+    if !summary.corrupted.is_empty() {
+        return Err(format!("{} corrupted files found", summary.corrupted.len()));
+    }
+    Ok(())
+}
+
+fn split_paths(paths: &[String]) -> Vec<String> {
+    // as the multi-line `path` input of actions/cache
+    paths
+        .iter()
+        .flat_map(|x| x.split('\n'))
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+fn ingest(
+    ns: &Namespace,
+    file: &str,
+    key: &str,
+    version: Option<String>,
+    components: Option<VersionComponents>,
+    overwrite: bool,
+) -> Result<(), String> {
+    let version = version.unwrap_or_else(|| components.as_ref().unwrap().version());
+
+    // the same restrictions as actions/cache on keys
+    if key.is_empty() || key.len() > 512 || key.contains(',') {
+        return Err(format!("invalid key: {key}"));
+    }
+    // each a directory of its own; "a/b" would be stored as key "a" and version "b"
+    if !is_path_component(key) || !is_path_component(&version) {
+        return Err(format!("invalid key or version: {key}/{version}"));
+    }
+    let path = format!("{key}/{version}");
+
+    let dst = format!("{}/{path}", ns.caches());
+    if load_metadata(&dst).is_some() && !overwrite {
+        return Err(format!(
+            "cache {path} already exists; give --overwrite to replace it"
+        ));
+    }
+
+    // stored as it is; the archive is already compressed
+    let mut input = std::fs::File::open(file).map_err(|e| format!("failed to open {file}: {e}"))?;
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(&mut input, &mut hasher)
+        .map_err(|e| format!("failed to read {file}: {e}"))?;
+
+    let now = now();
+    let metadata = FileMetadata {
+        size: size as usize,
+        created_at: now,
+        last_accessed_at: now,
+        sha256: Some(format!("{:x}", hasher.finalize())),
+        version_components: components,
+        ..Default::default()
+    };
+
+    let mut input = std::fs::File::open(file).map_err(|e| format!("failed to open {file}: {e}"))?;
+    import_file(&dst, &metadata, &mut input).map_err(|e| format!("failed to save {path}: {e}"))?;
+
+    println!(
+        "ingested {file} as cache {key} ({}), version {version}, {size} bytes",
+        ns.name
+    );
+    Ok(())
+}
+
+fn show_stats() -> Result<(), String> {
+    println!(
+        "{:<24} {:>8} {:>14} {:>6} {:>10} {:>14}",
+        "NAMESPACE", "CACHES", "CACHE SIZE", "RUNS", "ARTIFACTS", "ARTIFACT SIZE"
+    );
+
+    let mut total = [0; 5];
+    for ns in list_namespaces() {
+        let caches = list_caches(&ns, "");
+        let cache_size: usize = caches.iter().map(|x| x.metadata.size).sum();
+
+        let runs = list_runs(&ns);
+        let artifacts: Vec<_> = runs.iter().flat_map(|x| list_artifacts(&ns, x)).collect();
+        let artifact_size: usize = artifacts.iter().map(|x| x.size).sum();
+
+        let row = [
+            caches.len(),
+            cache_size,
+            runs.len(),
+            artifacts.len(),
+            artifact_size,
+        ];
+        print_stats_row(&ns.name, &row);
+
+        for (acc, x) in total.iter_mut().zip(row) {
+            *acc += x;
+        }
+    }
+    print_stats_row("(total)", &total);
+
+    Ok(())
+}
+
+fn print_stats_row(name: &str, row: &[usize; 5]) {
+    println!(
+        "{name:<24} {:>8} {:>14} {:>6} {:>10} {:>14}",
+        row[0], row[1], row[2], row[3], row[4]
+    );
+}
+
+fn list_all_runs(ns: &Namespace) -> Result<(), String> {
+    println!("{:<24} {:>9} {:>14}", "RUN", "ARTIFACTS", "SIZE");
+    for run_id in list_runs(ns) {
+        let artifacts = list_artifacts(ns, &run_id);
+        let size: usize = artifacts.iter().map(|x| x.size).sum();
+        println!("{run_id:<24} {:>9} {size:>14}", artifacts.len());
+    }
+    Ok(())
+}
+
+fn list_artifacts_of_run(ns: &Namespace, run_id: &str) -> Result<(), String> {
+    let artifacts = list_artifacts(ns, run_id);
+    if artifacts.is_empty() {
+        return Err(format!("no artifacts found for run {run_id}"));
+    }
+
+    println!(
+        "{:<32} {:>6} {:>14} {:<20}",
+        "NAME", "FILES", "SIZE", "CREATED"
+    );
+    for artifact in &artifacts {
+        println!(
+            "{:<32} {:>6} {:>14} {:<20}",
+            artifact.name,
+            artifact.files.len(),
+            artifact.size,
+            format_time(artifact.created_at)
+        );
+    }
+    Ok(())
+}
+
+fn download_artifact(
+    ns: &Namespace,
+    run_id: &str,
+    name: &str,
+    output: Option<String>,
+) -> Result<(), String> {
+    let entry = find_artifact(ns, run_id, name)
+        .ok_or_else(|| format!("artifact {name} not found in run {run_id}"))?;
+
+    // written as it is made, as the server sends it; a partial archive is not left behind
+    let output = output.unwrap_or_else(|| format!("{name}.zip"));
+    let file =
+        std::fs::File::create(&output).map_err(|e| format!("failed to write {output}: {e}"))?;
+    let mut file = std::io::BufWriter::new(file);
+    let written = write_artifact_zip(ns, &entry, &mut file).and_then(|_| file.flush());
+    if let Err(e) = written {
+        drop(file);
+        let _ = std::fs::remove_file(&output);
+        return Err(format!("failed to archive {name}: {e}"));
+    }
+
+    let size = std::fs::metadata(&output).map(|x| x.len()).unwrap_or(0);
+    println!("saved {output} ({size} bytes)");
+    Ok(())
+}
@@ -0,0 +1,312 @@
+use crate::cache::OverwritePolicy;
+use crate::file::Compression;
+use crate::logging::LogFormat;
+use crate::namespace::Namespace;
+use clap::Args;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+
+// server options; each of them is taken from the command line, the environment variable, the config
+// file, and the default, in this order
+#[derive(Args, Clone, Debug)]
+pub struct ServeArgs {
+    #[clap(
+        short,
+        long,
+        help = "Config file (TOML)",
+        env = "ACT_LOCAL_CACHE_SERVER_CONFIG"
+    )]
+    pub config: Option<String>,
+
+    #[clap(
+        short,
+        long,
+        help = "Server address [default: 127.0.0.1]",
+        env = "ACT_LOCAL_CACHE_SERVER_ADDRESS"
+    )]
+    pub address: Option<Ipv4Addr>,
+
+    #[clap(
+        short,
+        long,
+        help = "Server port [default: 8000]",
+        env = "ACT_LOCAL_CACHE_SERVER_PORT"
+    )]
+    pub port: Option<u16>,
+
+    #[clap(
+        short,
+        long,
+        help = "Authentication token [default: token]",
+        env = "ACT_LOCAL_CACHE_SERVER_TOKEN",
+        hide_env_values = true
+    )]
+    pub token: Option<String>,
+
+    #[clap(
+        long,
+        help = "Authentication token for the admin API (defaults to the token)",
+        env = "ACT_LOCAL_CACHE_SERVER_ADMIN_TOKEN",
+        hide_env_values = true
+    )]
+    pub admin_token: Option<String>,
+
+    #[clap(
+        long,
+        help = "Maximum total size of caches in bytes, applied to each namespace",
+        env = "ACT_LOCAL_CACHE_SERVER_NAMESPACE_QUOTA"
+    )]
+    pub namespace_quota: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Free space in bytes required to report readiness [default: 104857600]",
+        env = "ACT_LOCAL_CACHE_SERVER_MIN_FREE_SPACE"
+    )]
+    pub min_free_space: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Maximum size of an uploaded chunk in bytes [default: 67108864]",
+        env = "ACT_LOCAL_CACHE_SERVER_MAX_CHUNK_SIZE"
+    )]
+    pub max_chunk_size: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Maximum size of a cache entry in bytes [default: 10737418240]",
+        env = "ACT_LOCAL_CACHE_SERVER_MAX_CACHE_SIZE"
+    )]
+    pub max_cache_size: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Compression of stored caches and artifacts [default: none]",
+        value_enum,
+        env = "ACT_LOCAL_CACHE_SERVER_COMPRESSION"
+    )]
+    pub compression: Option<Compression>,
+
+    #[clap(
+        long,
+        help = "Whether a cache can be saved again under the same key and version [default: deny]",
+        value_enum,
+        env = "ACT_LOCAL_CACHE_SERVER_OVERWRITE"
+    )]
+    pub overwrite: Option<OverwritePolicy>,
+
+    #[clap(
+        long,
+        help = "Return a compatible version of a cache when the exact version is missing",
+        num_args = 0..=1,
+        default_missing_value = "true",
+        env = "ACT_LOCAL_CACHE_SERVER_COMPATIBLE_VERSIONS"
+    )]
+    pub compatible_versions: Option<bool>,
+
+    #[clap(
+        long,
+        help = "URL of a server to look up caches missing locally",
+        env = "ACT_LOCAL_CACHE_SERVER_UPSTREAM"
+    )]
+    pub upstream: Option<String>,
+
+    #[clap(
+        long,
+        help = "Authentication token for the upstream server",
+        env = "ACT_LOCAL_CACHE_SERVER_UPSTREAM_TOKEN",
+        hide_env_values = true
+    )]
+    pub upstream_token: Option<String>,
+
+    #[clap(
+        long,
+        help = "Also upload finalized caches to the upstream server",
+        num_args = 0..=1,
+        default_missing_value = "true",
+        env = "ACT_LOCAL_CACHE_SERVER_UPSTREAM_WRITE_THROUGH"
+    )]
+    pub upstream_write_through: Option<bool>,
+
+    #[clap(
+        long,
+        help = "Log format [default: text]",
+        value_enum,
+        env = "ACT_LOCAL_CACHE_SERVER_LOG_FORMAT"
+    )]
+    pub log_format: Option<LogFormat>,
+
+    #[clap(
+        long,
+        help = "Append logs to the file instead of stderr",
+        env = "ACT_LOCAL_CACHE_SERVER_LOG_FILE"
+    )]
+    pub log_file: Option<String>,
+
+    #[clap(
+        long,
+        help = "Seconds to wait for reserved uploads to be finalized on shutdown [default: 30]",
+        env = "ACT_LOCAL_CACHE_SERVER_SHUTDOWN_TIMEOUT"
+    )]
+    pub shutdown_timeout: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamespaceConfig {
+    pub quota: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub address: Ipv4Addr,
+    pub port: u16,
+    pub token: String,
+    pub admin_token: Option<String>,
+    pub namespace_quota: Option<usize>,
+    pub min_free_space: u64,
+    pub max_chunk_size: u64,
+    pub max_cache_size: usize,
+    pub compression: Compression,
+    pub overwrite: OverwritePolicy,
+    pub compatible_versions: bool,
+    pub upstream: Option<String>,
+    pub upstream_token: Option<String>,
+    pub upstream_write_through: bool,
+    pub log_format: LogFormat,
+    pub log_file: Option<String>,
+    pub shutdown_timeout: u64, // seconds
+
+    // per-namespace overrides, as [namespaces.<name>]
+    pub namespaces: BTreeMap<String, NamespaceConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            address: Ipv4Addr::new(127, 0, 0, 1),
+            port: 8000,
+            token: "token".to_string(),
+            admin_token: None,
+            namespace_quota: None,
+            min_free_space: 100 * 1024 * 1024,
+            max_chunk_size: 64 * 1024 * 1024,
+            max_cache_size: 10 * 1024 * 1024 * 1024, // as GitHub
+            compression: Compression::None,
+            overwrite: OverwritePolicy::Deny,
+            compatible_versions: false,
+            upstream: None,
+            upstream_token: None,
+            upstream_write_through: false,
+            log_format: LogFormat::Text,
+            log_file: None,
+            shutdown_timeout: 30,
+            namespaces: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(args: &ServeArgs) -> Result<Config, String> {
+        let mut config = if let Some(path) = &args.config {
+            let toml = std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read config file {path}: {e}"))?;
+            toml::from_str(&toml).map_err(|e| format!("failed to parse config file {path}: {e}"))?
+        } else {
+            Config::default()
+        };
+
+        // the command line and environment variables take precedence over the file
+        let args = args.clone();
+        config.address = args.address.unwrap_or(config.address);
+        config.port = args.port.unwrap_or(config.port);
+        config.token = args.token.unwrap_or(config.token);
+        config.admin_token = args.admin_token.or(config.admin_token);
+        config.namespace_quota = args.namespace_quota.or(config.namespace_quota);
+        config.min_free_space = args.min_free_space.unwrap_or(config.min_free_space);
+        config.max_chunk_size = args.max_chunk_size.unwrap_or(config.max_chunk_size);
+        config.max_cache_size = args.max_cache_size.unwrap_or(config.max_cache_size);
+        config.compression = args.compression.unwrap_or(config.compression);
+        config.overwrite = args.overwrite.unwrap_or(config.overwrite);
+        config.compatible_versions = args
+            .compatible_versions
+            .unwrap_or(config.compatible_versions);
+        config.upstream = args.upstream.or(config.upstream);
+        config.upstream_token = args.upstream_token.or(config.upstream_token);
+        config.upstream_write_through = args
+            .upstream_write_through
+            .unwrap_or(config.upstream_write_through);
+
+        // "http://host:port/" and "http://host:port" are the same
+        config.upstream = config.upstream.map(|x| x.trim_end_matches('/').to_string());
+        config.log_format = args.log_format.unwrap_or(config.log_format);
+        config.log_file = args.log_file.or(config.log_file);
+        config.shutdown_timeout = args.shutdown_timeout.unwrap_or(config.shutdown_timeout);
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.port == 0 {
+            return Err("port must not be 0".to_string());
+        }
+        if self.token.is_empty() || self.token.contains(char::is_whitespace) {
+            return Err("token must be non-empty and must not contain whitespace".to_string());
+        }
+        if let Some(token) = &self.admin_token {
+            if token.is_empty() || token.contains(char::is_whitespace) {
+                return Err(
+                    "admin_token must be non-empty and must not contain whitespace".to_string(),
+                );
+            }
+        }
+        if self.max_chunk_size == 0 {
+            return Err("max_chunk_size must be larger than 0".to_string());
+        }
+        if self.max_cache_size == 0 {
+            return Err("max_cache_size must be larger than 0".to_string());
+        }
+        if let Some(upstream) = &self.upstream {
+            if !upstream.starts_with("http://") && !upstream.starts_with("https://") {
+                return Err(format!("upstream must be an http(s) URL: {upstream}"));
+            }
+        }
+        if self.upstream_write_through && self.upstream.is_none() {
+            return Err("upstream_write_through requires upstream".to_string());
+        }
+        if self.namespace_quota == Some(0) {
+            return Err("namespace_quota must be larger than 0".to_string());
+        }
+        for (name, namespace) in &self.namespaces {
+            if Namespace::open(name).is_none() {
+                return Err(format!("invalid namespace name: {name}"));
+            }
+            if namespace.quota == Some(0) {
+                return Err(format!("quota of namespace {name} must be larger than 0"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn admin_token(&self) -> &str {
+        self.admin_token.as_deref().unwrap_or(&self.token)
+    }
+
+    pub fn quota(&self, namespace: &str) -> Option<usize> {
+        let namespace = self.namespaces.get(namespace);
+        namespace.and_then(|x| x.quota).or(self.namespace_quota)
+    }
+
+    pub fn to_masked_toml(&self) -> String {
+        // for `config check`; tokens are not printed
+        let mut config = self.clone();
+        config.token = "********".to_string();
+        config.admin_token = config.admin_token.map(|_| "********".to_string());
+        config.upstream_token = config.upstream_token.map(|_| "********".to_string());
+
+        toml::to_string_pretty(&config).unwrap()
+    }
+}
@@ -1,16 +1,196 @@
-use crate::utils::glob_in;
+use crate::utils::{accepts_encoding, glob_in, now};
+use crate::version::VersionComponents;
+use clap::ValueEnum;
+use flate2::read::MultiGzDecoder;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::ops::Range;
-use std::path::Path;
+use std::path::{Component, Path};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
 
-pub fn save_file(path: &str, is_gzip: bool, start: usize, input: &[u8]) {
-    // workaround for gzipped stream
-    let gz = if is_gzip { ".gzippedStream" } else { "" };
+// sidecar file holding what the server knows about a finalized file
+const METADATA_SUFFIX: &str = ".fileMetadata";
+
+// next to the directory of an artifact; the container created for it
+pub const CONTAINER_SUFFIX: &str = ".containerMetadata";
+
+// concatenated chunks are renamed to the final path only when complete
+const FINALIZE_SUFFIX: &str = ".finalizeTemporary";
+
+// compression applied by the server to the stored files
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct FileMetadata {
+    pub size: usize,     // as uploaded
+    pub created_at: u64, // seconds since the epoch
+    pub last_accessed_at: u64,
+    pub hits: u64, // downloads
+
+    // "gzip" for files uploaded as a gzipped stream; replayed on download
+    pub content_encoding: Option<String>,
+    pub compression: Compression,
+
+    // hex of the uploaded contents; missing for files finalized by older versions
+    pub sha256: Option<String>,
+
+    // how the version of a cache was computed, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_components: Option<VersionComponents>,
+}
+
+pub fn is_metadata(path: &str) -> bool {
+    path.ends_with(METADATA_SUFFIX) || path.ends_with(CONTAINER_SUFFIX)
+}
+
+pub fn is_temporary(path: &str) -> bool {
+    path.contains(".uploadTemporary.") || path.ends_with(FINALIZE_SUFFIX)
+}
+
+pub fn is_safe_path(path: &str) -> bool {
+    // relative paths given from outside must stay inside the data directory
+    !path.is_empty()
+        && !is_metadata(path)
+        && !is_temporary(path)
+        && Path::new(path)
+            .components()
+            .all(|x| matches!(x, Component::Normal(_)))
+}
+
+pub fn is_path_component(name: &str) -> bool {
+    // a single directory or file name given from outside, e.g. a run id or an artifact name
+    !name.contains('/') && is_safe_path(name)
+}
+
+fn stored_path(path: &str) -> (bool, String) {
+    // files uploaded as a gzipped stream used to be saved with this suffix
+    let gzipped = format!("{path}.gzippedStream");
+    if Path::new(&gzipped).exists() {
+        (true, gzipped)
+    } else {
+        (false, path.to_string())
+    }
+}
+
+pub fn load_metadata(path: &str) -> Option<FileMetadata> {
+    let (is_gzip, path) = stored_path(path);
+    let legacy_encoding = is_gzip.then(|| "gzip".to_string());
+
+    if let Ok(json) = std::fs::read(format!("{path}{METADATA_SUFFIX}")) {
+        let mut metadata: FileMetadata = serde_json::from_slice(&json).ok()?;
+        metadata.content_encoding = metadata.content_encoding.or(legacy_encoding);
+        return Some(metadata);
+    }
+
+    // files finalized before the metadata was introduced; recover what we can from the filesystem
+    let metadata = std::fs::metadata(&path).ok()?;
+    let mtime = metadata.modified().ok()?;
+    let mtime = mtime.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs();
+
+    Some(FileMetadata {
+        size: metadata.len() as usize,
+        created_at: mtime,
+        last_accessed_at: mtime,
+        hits: 0,
+        content_encoding: legacy_encoding,
+        compression: Compression::None,
+        sha256: None,
+        version_components: None,
+    })
+}
+
+pub fn save_metadata(path: &str, metadata: &FileMetadata) {
+    let (_, path) = stored_path(path);
+    let json = serde_json::to_vec(metadata).unwrap();
+    std::fs::write(format!("{path}{METADATA_SUFFIX}"), json).unwrap();
+}
 
+pub fn touch_metadata(path: &str) {
+    if let Some(mut metadata) = load_metadata(path) {
+        metadata.last_accessed_at = now();
+        metadata.hits += 1;
+        save_metadata(path, &metadata);
+    }
+}
+
+pub fn remove_file_and_metadata(path: &str) -> bool {
+    let (_, path) = stored_path(path);
+    let _ = std::fs::remove_file(format!("{path}{METADATA_SUFFIX}"));
+    std::fs::remove_file(&path).is_ok()
+}
+
+pub fn move_file_and_metadata(path: &str, dst: &str) -> std::io::Result<()> {
+    // `dst` takes the suffix of the stored file, if any
+    let (_, src) = stored_path(path);
+    let dst = format!("{dst}{}", &src[path.len()..]);
+
+    let (dir, _) = dst.rsplit_once('/').unwrap();
+    std::fs::create_dir_all(dir)?;
+    std::fs::rename(&src, &dst)?;
+
+    let _ = std::fs::rename(
+        format!("{src}{METADATA_SUFFIX}"),
+        format!("{dst}{METADATA_SUFFIX}"),
+    );
+    Ok(())
+}
+
+pub fn open_stored_file(path: &str) -> std::io::Result<std::fs::File> {
+    // the file as stored, e.g. compressed; to be read along with its metadata
+    let (_, path) = stored_path(path);
+    std::fs::File::open(path)
+}
+
+pub fn import_file<R: Read>(
+    path: &str,
+    metadata: &FileMetadata,
+    input: &mut R,
+) -> std::io::Result<u64> {
+    // saves a file as stored elsewhere, in the same order as finalize_files
+    let (dir, _) = path.rsplit_once('/').unwrap();
+    std::fs::create_dir_all(dir)?;
+
+    let tmp = format!("{path}{FINALIZE_SUFFIX}");
+    let mut file = std::fs::File::create(&tmp)?;
+    let size = std::io::copy(input, &mut file)?;
+    file.sync_all()?;
+
+    let (is_legacy, legacy) = stored_path(path);
+    if is_legacy {
+        remove_file_and_metadata(&legacy);
+    }
+
+    save_metadata(path, metadata);
+    std::fs::rename(&tmp, path)?;
+    Ok(size)
+}
+
+pub fn remove_empty_dirs(dir: &str, path: &str) {
+    // remove now-empty parent directories of `path` up to (excluding) `dir`
+    let mut path = Path::new(path);
+    while let Some(parent) = path.parent() {
+        if parent == Path::new(dir) || std::fs::remove_dir(parent).is_err() {
+            break;
+        }
+        path = parent;
+    }
+}
+
+pub fn save_file(path: &str, is_gzip: bool, start: usize, input: &[u8]) {
     // format chunk prefix that can be safely sorted into the original chunk order
-    // (this assumes total bytes being less than 1TB)
-    let path = format!("{path}{gz}.uploadTemporary.{start:012}");
+    // (this assumes total bytes being less than 1TB); gzipped chunks are marked to be recorded on finalize
+    let gz = if is_gzip { ".gzip" } else { "" };
+    let path = format!("{path}.uploadTemporary.{start:012}{gz}");
 
     let (dir, _) = path.rsplit_once('/').unwrap();
     std::fs::create_dir_all(dir).unwrap();
@@ -25,7 +205,7 @@ pub fn save_file(path: &str, is_gzip: bool, start: usize, input: &[u8]) {
     file.write_all(input).unwrap();
 }
 
-pub fn finalize_files(dir: &str, pattern: &str) -> usize {
+pub fn finalize_files(dir: &str, pattern: &str, compression: Compression) -> usize {
     let paths = glob_in(dir, &format!("{pattern}.uploadTemporary.*")).unwrap();
 
     // group by basename
@@ -49,41 +229,141 @@ pub fn finalize_files(dir: &str, pattern: &str) -> usize {
     for (dst, srcs) in &mut map {
         srcs.sort();
 
-        let dst = format!("{dir}/{dst}");
-        let mut dst = std::fs::OpenOptions::new()
+        // gzipped streams are not compressed again
+        let is_gzip = srcs.iter().any(|x| x.ends_with(".gzip"));
+        let compression = if is_gzip {
+            Compression::None
+        } else {
+            compression
+        };
+
+        let path = format!("{dir}/{dst}");
+        let tmp = format!("{path}{FINALIZE_SUFFIX}");
+        let file = std::fs::OpenOptions::new()
             .read(false)
             .write(true)
             .create(true)
             .truncate(true)
-            .open(&dst)
+            .open(&tmp)
             .unwrap();
+        let mut dst: Box<dyn Write> = match compression {
+            Compression::None => Box::new(file.try_clone().unwrap()),
+            Compression::Zstd => Box::new(
+                zstd::Encoder::new(file.try_clone().unwrap(), 0)
+                    .unwrap()
+                    .auto_finish(),
+            ),
+        };
 
-        for src in srcs {
+        let mut size = 0;
+        let mut hasher = Sha256::new();
+        for src in srcs.iter() {
             buf.clear();
 
-            let src = format!("{dir}/{src}");
-            {
-                let mut src = std::fs::File::open(&src).unwrap();
-                acc += src.read_to_end(&mut buf).unwrap();
-            }
-
+            let mut src = std::fs::File::open(format!("{dir}/{src}")).unwrap();
+            size += src.read_to_end(&mut buf).unwrap();
+            hasher.update(&buf);
             dst.write_all(&buf).unwrap();
-            std::fs::remove_file(&src).unwrap();
         }
-        dst.sync_all().unwrap();
+        drop(dst);
+        file.sync_all().unwrap();
+        acc += size;
+
+        // a file uploaded again replaces the one saved with the old suffix
+        let (is_legacy, legacy) = stored_path(&path);
+        if is_legacy {
+            remove_file_and_metadata(&legacy);
+        }
+
+        // the metadata goes first, as the file cannot be read correctly without it
+        let now = now();
+        let metadata = FileMetadata {
+            size,
+            created_at: now,
+            last_accessed_at: now,
+            hits: 0,
+            content_encoding: is_gzip.then(|| "gzip".to_string()),
+            compression,
+            sha256: Some(format!("{:x}", hasher.finalize())),
+            version_components: None,
+        };
+        save_metadata(&path, &metadata);
+
+        // chunks are kept until the file is complete, so that an interrupted finalize can be retried
+        std::fs::rename(&tmp, &path).unwrap();
+        for src in srcs.iter() {
+            std::fs::remove_file(format!("{dir}/{src}")).unwrap();
+        }
     }
 
     acc
 }
 
+pub fn count_pending_uploads(dir: &str) -> usize {
+    // number of files that have chunks not finalized yet
+    let paths = glob_in(dir, "**/*.uploadTemporary.*").unwrap_or_default();
+
+    let mut basenames: Vec<_> = paths
+        .iter()
+        .filter_map(|x| x.to_str()?.split_once(".uploadTemporary."))
+        .map(|(basename, _)| basename.to_string())
+        .collect();
+    basenames.sort();
+    basenames.dedup();
+    basenames.len()
+}
+
+fn modified_at(path: &str) -> Option<u64> {
+    let mtime = std::fs::metadata(path).and_then(|x| x.modified()).ok()?;
+    Some(mtime.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs())
+}
+
+pub fn last_upload_activity(dir: &str) -> Option<u64> {
+    // when a chunk was last written, or a container created, under the directory
+    let paths = glob_in(dir, "**/*").unwrap_or_default();
+
+    paths
+        .iter()
+        .map(|x| format!("{dir}/{}", x.to_str().unwrap()))
+        .filter(|x| is_temporary(x) || x.ends_with(CONTAINER_SUFFIX))
+        .filter_map(|x| modified_at(&x))
+        .max()
+}
+
+pub fn remove_stale_chunks(dir: &str, before: u64) -> usize {
+    // chunks of uploads that were never finalized, and files left by an interrupted finalize
+    let paths = glob_in(dir, "**/*").unwrap_or_default();
+
+    let mut count = 0;
+    for path in &paths {
+        let path = format!("{dir}/{}", path.to_str().unwrap());
+        if !is_temporary(&path) {
+            continue;
+        }
+
+        let Some(mtime) = modified_at(&path) else {
+            continue;
+        };
+
+        if mtime < before && std::fs::remove_file(&path).is_ok() {
+            remove_empty_dirs(dir, &path);
+            count += 1;
+        }
+    }
+    count
+}
+
 pub fn list_all_files(dir: &str) -> Vec<String> {
     let paths = glob_in(dir, "**/*").unwrap();
 
     let mut array = Vec::new();
     for path in &paths {
         let path = path.to_str().unwrap();
+        if is_metadata(path) || is_temporary(path) {
+            continue;
+        }
 
-        // workaround for gzipped stream; remove if the file has the .gzippedStream prefix
+        // files saved with the old suffix for gzipped streams
         let path = path.strip_suffix(".gzippedStream").unwrap_or(path);
 
         if !Path::new(&format!("{dir}/{path}")).is_dir() {
@@ -93,29 +373,158 @@ pub fn list_all_files(dir: &str) -> Vec<String> {
     array
 }
 
-pub fn dump_file(path: &str, range: Option<Range<usize>>) -> (bool, Vec<u8>) {
-    // workaround for gzipped stream
-    let gzipped = format!("{path}.gzippedStream");
-    let (is_gzip, path) = if Path::new(&gzipped).exists() {
-        (true, gzipped.as_str())
-    } else {
-        (false, path)
+pub fn disk_usage(dir: &str) -> (usize, usize) {
+    // (number of files, total bytes) including chunks not finalized yet
+    let paths = glob_in(dir, "**/*").unwrap_or_default();
+
+    let mut count = 0;
+    let mut size = 0;
+    for path in &paths {
+        let path = format!("{dir}/{}", path.to_str().unwrap());
+        if is_metadata(&path) {
+            continue;
+        }
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            if metadata.is_file() {
+                count += 1;
+                size += metadata.len() as usize;
+            }
+        }
+    }
+    (count, size)
+}
+
+fn read_contents(path: &str, metadata: &FileMetadata) -> std::io::Result<Vec<u8>> {
+    // the whole file as uploaded
+    let (_, path) = stored_path(path);
+    let file = std::fs::File::open(path)?;
+    match metadata.compression {
+        Compression::None => {
+            let mut buf = Vec::new();
+            (&file).read_to_end(&mut buf)?;
+            Ok(buf)
+        }
+        Compression::Zstd => zstd::decode_all(file),
+    }
+}
+
+pub fn open_contents(path: &str) -> Result<(FileMetadata, Box<dyn Read + Send>), String> {
+    // the whole file as uploaded, as a stream; unlike dump_file, the checksum is left to the reader
+    let metadata = load_metadata(path).ok_or("no such file")?;
+    let (_, path) = stored_path(path);
+    let file = std::fs::File::open(path).map_err(|e| format!("unreadable: {e}"))?;
+    let reader: Box<dyn Read + Send> = match metadata.compression {
+        Compression::None => Box::new(file),
+        Compression::Zstd => {
+            Box::new(zstd::Decoder::new(file).map_err(|e| format!("unreadable: {e}"))?)
+        }
+    };
+    Ok((metadata, reader))
+}
+
+fn check_sha256(metadata: &FileMetadata, data: &[u8]) -> Result<(), String> {
+    let Some(expected) = &metadata.sha256 else {
+        return Ok(());
     };
 
-    let mut file = std::fs::File::open(path).unwrap();
+    let actual = format!("{:x}", Sha256::digest(data));
+    if &actual != expected {
+        return Err(format!(
+            "checksum mismatch (expected = {expected}, actual = {actual})"
+        ));
+    }
+    Ok(())
+}
+
+pub fn verify_file(path: &str) -> Result<Option<FileMetadata>, String> {
+    // -> metadata if the checksum matched, None if the file has no checksum recorded
+    let metadata = load_metadata(path).ok_or("no such file")?;
+    if metadata.sha256.is_none() {
+        return Ok(None);
+    }
+
+    let data = read_contents(path, &metadata).map_err(|e| format!("unreadable: {e}"))?;
+    check_sha256(&metadata, &data)?;
+    Ok(Some(metadata))
+}
+
+pub fn dump_file(
+    path: &str,
+    range: Option<Range<usize>>,
+    accept_encoding: Option<&str>,
+) -> Result<(Option<String>, Vec<u8>), String> {
+    // -> (Content-Encoding, body)
+    let metadata = load_metadata(path).ok_or("no such file")?;
+
+    let Some(range) = range else {
+        // the whole file is checked against the checksum recorded on finalize
+        let buf = read_contents(path, &metadata).map_err(|e| format!("unreadable: {e}"))?;
+        check_sha256(&metadata, &buf)?;
+
+        // compressed files are sent as they are to clients accepting the compression
+        let accepts = |x: &str| accept_encoding.is_some_and(|y| accepts_encoding(y, x));
+        if metadata.compression == Compression::Zstd
+            && metadata.content_encoding.is_none()
+            && accepts("zstd")
+        {
+            let (_, path) = stored_path(path);
+            let buf = std::fs::read(path).map_err(|e| format!("unreadable: {e}"))?;
+            return Ok((Some("zstd".to_string()), buf));
+        }
 
-    // slice the specified range
-    if let Some(range) = range {
+        // gzipped streams are replayed, unless the client does not accept gzip
+        return match metadata.content_encoding {
+            Some(encoding) if accept_encoding.is_some() && !accepts(&encoding) => {
+                let buf = decode_gzip(&buf).map_err(|e| format!("undecodable: {e}"))?;
+                Ok((None, buf))
+            }
+            encoding => Ok((encoding, buf)),
+        };
+    };
+
+    // ranges are of the uploaded contents, and not checked
+    let buf = if metadata.compression == Compression::Zstd {
+        let buf = read_contents(path, &metadata).map_err(|e| format!("unreadable: {e}"))?;
+        buf[range.start.min(buf.len())..range.end.min(buf.len())].to_vec()
+    } else {
+        // slice the specified range
+        let (_, path) = stored_path(path);
+        let mut file = std::fs::File::open(path).unwrap();
         file.seek(SeekFrom::Start(range.start as u64)).unwrap();
 
         let mut buf = vec![0; range.len()];
         let len = file.read(&mut buf).unwrap();
         buf.truncate(len);
+        buf
+    };
+    Ok((metadata.content_encoding, buf))
+}
 
-        (is_gzip, buf)
-    } else {
-        let mut buf = Vec::new();
-        file.read_to_end(&mut buf).unwrap();
-        (is_gzip, buf)
+pub fn decode_gzip(input: &[u8]) -> std::io::Result<Vec<u8>> {
+    // uploads tagged as gzip are not checked on the way in, so this may fail
+    let mut buf = Vec::new();
+    MultiGzDecoder::new(input).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+pub fn write_zip<W: Write>(dir: &str, files: &[String], output: W) -> std::io::Result<usize> {
+    // the archive holds the original contents, so gzipped streams are decoded before compressed again
+    let mut zip = ZipWriter::new_stream(output);
+
+    let mut acc = 0;
+    for file in files {
+        let (_, data) = dump_file(&format!("{dir}/{file}"), None, Some("identity"))
+            .map_err(|e| std::io::Error::other(format!("{file}: {e}")))?;
+
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .large_file(data.len() >= u32::MAX as usize);
+        zip.start_file(file.as_str(), options)?;
+        zip.write_all(&data)?;
+
+        acc += data.len();
     }
+    zip.finish()?;
+
+    Ok(acc)
 }
@@ -0,0 +1,241 @@
+use crate::cache::{cache_id, list_caches, remove_cache, CacheEntry};
+use crate::namespace::Namespace;
+use crate::utils::format_time;
+use log::info;
+use serde_derive::{Deserialize, Serialize};
+use warp::http::StatusCode;
+use warp::reply::{json, with_status, Json, Reply, Response, WithStatus};
+
+// GitHub REST API error body
+#[derive(Serialize, Clone, Debug)]
+struct MessageResponse {
+    message: String,
+}
+
+fn error(name: &str, message: &str, status: StatusCode) -> WithStatus<Json> {
+    let res = MessageResponse {
+        message: message.to_string(),
+    };
+    info!("[{name}] response = {res:?}");
+
+    with_status(json(&res), status)
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ActionsCache {
+    id: u64,
+
+    // refs are not recorded by the cache protocol
+    #[serde(rename = "ref")]
+    git_ref: String,
+
+    key: String,
+    version: String,
+    last_accessed_at: String,
+    created_at: String,
+    size_in_bytes: usize,
+}
+
+impl From<&CacheEntry> for ActionsCache {
+    fn from(entry: &CacheEntry) -> ActionsCache {
+        ActionsCache {
+            id: cache_id(&entry.key, &entry.version),
+            git_ref: String::new(),
+            key: entry.key.clone(),
+            version: entry.version.clone(),
+            last_accessed_at: format_time(entry.metadata.last_accessed_at),
+            created_at: format_time(entry.metadata.created_at),
+            size_in_bytes: entry.metadata.size,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ActionsCacheList {
+    total_count: usize,
+    actions_caches: Vec<ActionsCache>,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Clone, Debug)]
+pub struct ListCachesQuery {
+    key: Option<String>,
+
+    #[serde(rename = "ref")]
+    git_ref: Option<String>,
+
+    sort: Option<String>,
+    direction: Option<String>,
+    per_page: Option<usize>,
+    page: Option<usize>,
+}
+
+// GET /repos/<owner>/<repo>/actions/caches
+pub fn github_list_caches(
+    ns: Namespace,
+    owner: String,
+    repo: String,
+    query: ListCachesQuery,
+) -> WithStatus<Json> {
+    info!(
+        "[github_list_caches] namespace = {}, repo = {owner}/{repo}, query = {query:?}",
+        ns.name
+    );
+
+    // "key" is a prefix match as in GitHub; "ref" is accepted but not used since refs are not recorded
+    let prefix = query.key.unwrap_or_default();
+    let mut entries = list_caches(&ns, &prefix);
+
+    match query.sort.as_deref().unwrap_or("last_accessed_at") {
+        "created_at" => entries.sort_by_key(|x| x.metadata.created_at),
+        "last_accessed_at" => entries.sort_by_key(|x| x.metadata.last_accessed_at),
+        "size_in_bytes" => entries.sort_by_key(|x| x.metadata.size),
+        _ => {
+            return error(
+                "github_list_caches",
+                "Validation Failed",
+                StatusCode::UNPROCESSABLE_ENTITY,
+            )
+        }
+    }
+    match query.direction.as_deref().unwrap_or("desc") {
+        "asc" => {}
+        "desc" => entries.reverse(),
+        _ => {
+            return error(
+                "github_list_caches",
+                "Validation Failed",
+                StatusCode::UNPROCESSABLE_ENTITY,
+            )
+        }
+    }
+
+    let per_page = query.per_page.unwrap_or(30).clamp(1, 100);
+    let page = query.page.unwrap_or(1).max(1);
+
+    let total_count = entries.len();
+    let actions_caches = entries
+        .iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .map(ActionsCache::from)
+        .collect();
+
+    let res = ActionsCacheList {
+        total_count,
+        actions_caches,
+    };
+    info!(
+        "[github_list_caches] response = ActionsCacheList {{ total_count: {total_count}, actions_caches: <{} items> }}",
+        res.actions_caches.len()
+    );
+
+    with_status(json(&res), StatusCode::OK)
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Clone, Debug)]
+pub struct DeleteCachesQuery {
+    key: Option<String>,
+
+    #[serde(rename = "ref")]
+    git_ref: Option<String>,
+}
+
+// DELETE /repos/<owner>/<repo>/actions/caches?key=
+pub fn github_delete_caches_by_key(
+    ns: Namespace,
+    owner: String,
+    repo: String,
+    query: DeleteCachesQuery,
+) -> WithStatus<Json> {
+    info!(
+        "[github_delete_caches_by_key] namespace = {}, repo = {owner}/{repo}, query = {query:?}",
+        ns.name
+    );
+
+    let Some(key) = query.key else {
+        return error(
+            "github_delete_caches_by_key",
+            "Validation Failed",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        );
+    };
+
+    // exact match here, unlike the listing
+    let entries: Vec<_> = list_caches(&ns, &key)
+        .into_iter()
+        .filter(|x| x.key == key)
+        .collect();
+    if entries.is_empty() {
+        return error(
+            "github_delete_caches_by_key",
+            "Not Found",
+            StatusCode::NOT_FOUND,
+        );
+    }
+
+    for entry in &entries {
+        remove_cache(&ns, entry);
+    }
+
+    let res = ActionsCacheList {
+        total_count: entries.len(),
+        actions_caches: entries.iter().map(ActionsCache::from).collect(),
+    };
+    info!("[github_delete_caches_by_key] response = {res:?}");
+
+    with_status(json(&res), StatusCode::OK)
+}
+
+// DELETE /repos/<owner>/<repo>/actions/caches/<cache_id>
+pub fn github_delete_cache_by_id(ns: Namespace, owner: String, repo: String, id: u64) -> Response {
+    info!(
+        "[github_delete_cache_by_id] namespace = {}, repo = {owner}/{repo}, id = {id}",
+        ns.name
+    );
+
+    let entry = list_caches(&ns, "")
+        .into_iter()
+        .find(|x| cache_id(&x.key, &x.version) == id);
+
+    if let Some(entry) = entry {
+        remove_cache(&ns, &entry);
+
+        info!("[github_delete_cache_by_id] response = <no content>");
+        with_status(warp::reply(), StatusCode::NO_CONTENT).into_response()
+    } else {
+        error(
+            "github_delete_cache_by_id",
+            "Not Found",
+            StatusCode::NOT_FOUND,
+        )
+        .into_response()
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct CacheUsageResponse {
+    full_name: String,
+    active_caches_size_in_bytes: usize,
+    active_caches_count: usize,
+}
+
+// GET /repos/<owner>/<repo>/actions/cache/usage
+pub fn github_cache_usage(ns: Namespace, owner: String, repo: String) -> WithStatus<Json> {
+    info!(
+        "[github_cache_usage] namespace = {}, repo = {owner}/{repo}",
+        ns.name
+    );
+
+    let entries = list_caches(&ns, "");
+
+    let res = CacheUsageResponse {
+        full_name: format!("{owner}/{repo}"),
+        active_caches_size_in_bytes: entries.iter().map(|x| x.metadata.size).sum(),
+        active_caches_count: entries.len(),
+    };
+    info!("[github_cache_usage] response = {res:?}");
+
+    with_status(json(&res), StatusCode::OK)
+}
@@ -0,0 +1,76 @@
+use crate::namespace::DATA_DIR;
+use crate::shutdown::is_draining;
+use log::info;
+use serde_derive::Serialize;
+use warp::http::StatusCode;
+use warp::reply::{json, with_status, Json, WithStatus};
+
+#[derive(Serialize, Clone, Debug)]
+struct StatusResponse {
+    status: String,
+}
+
+// GET /healthz
+pub fn check_liveness() -> WithStatus<Json> {
+    let res = StatusResponse {
+        status: "ok".to_string(),
+    };
+    with_status(json(&res), StatusCode::OK)
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ReadinessResponse {
+    status: String,
+
+    #[serde(rename = "availableBytes")]
+    available: u64,
+}
+
+// GET /readyz
+pub fn check_readiness(min_free_space: u64) -> WithStatus<Json> {
+    let (status, available) = match probe_data_dir() {
+        _ if is_draining() => ("shutting down".to_string(), 0),
+        Err(e) => (format!("data directory is not writable: {e}"), 0),
+        Ok(available) if available < min_free_space => (
+            format!("free space is less than {min_free_space} bytes"),
+            available,
+        ),
+        Ok(available) => ("ready".to_string(), available),
+    };
+
+    let code = if status == "ready" {
+        StatusCode::OK
+    } else {
+        info!("[check_readiness] not ready: {status}");
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    let res = ReadinessResponse { status, available };
+    with_status(json(&res), code)
+}
+
+fn probe_data_dir() -> std::io::Result<u64> {
+    // write and remove a file to make sure uploads can be saved
+    std::fs::create_dir_all(DATA_DIR)?;
+
+    let probe = format!("{DATA_DIR}/.readinessProbe");
+    std::fs::write(&probe, b"ok")?;
+    std::fs::remove_file(&probe)?;
+
+    fs2::available_space(DATA_DIR)
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct VersionResponse {
+    name: String,
+    version: String,
+}
+
+// GET /version
+pub fn show_version() -> WithStatus<Json> {
+    let res = VersionResponse {
+        name: env!("CARGO_PKG_NAME").to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+    with_status(json(&res), StatusCode::OK)
+}
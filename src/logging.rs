@@ -0,0 +1,107 @@
+use crate::namespace::DEFAULT_NAMESPACE;
+use clap::ValueEnum;
+use serde_derive::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::field::Empty;
+use tracing::Span;
+use tracing_subscriber::EnvFilter;
+use warp::log::Info;
+use warp::trace::Info as TraceInfo;
+
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+pub fn init(format: LogFormat, file: Option<&str>) -> Result<(), String> {
+    // RUST_LOG overrides the default: errors, warnings of the server, and the access log. the
+    // request spans of warp log every 5xx as an error, which the access log already records
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new("error,warp::filters::trace=off,access=info,act_local_cache_server=warn")
+    });
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    // the `log` records of the handlers are forwarded to tracing, so that they are in the request span
+    match (format, file) {
+        (LogFormat::Text, None) => builder.init(),
+        (LogFormat::Json, None) => builder.json().with_current_span(true).init(),
+        (format, Some(file)) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file)
+                .map_err(|e| format!("failed to open log file {file}: {e}"))?;
+            let builder = builder.with_writer(Mutex::new(file)).with_ansi(false);
+
+            if format == LogFormat::Json {
+                builder.json().with_current_span(true).init();
+            } else {
+                builder.init();
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn request_span(info: TraceInfo) -> Span {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+    // reuse the id if the client (or a proxy) has assigned one
+    let request_id = info
+        .request_headers()
+        .get("X-Request-Id")
+        .and_then(|x| x.to_str().ok())
+        .map(|x| x.to_string())
+        .unwrap_or_else(|| format!("{:08x}", NEXT_ID.fetch_add(1, Ordering::Relaxed)));
+
+    let namespace = info
+        .path()
+        .strip_prefix("/ns/")
+        .and_then(|x| x.split('/').next())
+        .unwrap_or(DEFAULT_NAMESPACE);
+
+    // the fields left empty are filled by the handlers
+    tracing::info_span!(
+        target: "access",
+        "request",
+        request_id = %request_id,
+        method = %info.method(),
+        route = %info.path(),
+        namespace = %namespace,
+        cache_key = Empty,
+        run_id = Empty,
+        bytes = Empty,
+    )
+}
+
+pub fn access_log(info: Info) {
+    let request_bytes = info
+        .request_headers()
+        .get("Content-Length")
+        .and_then(|x| x.to_str().ok()?.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    // method and route are in the request span
+    tracing::info!(
+        target: "access",
+        status = info.status().as_u16(),
+        request_bytes,
+        latency_ms = info.elapsed().as_secs_f64() * 1000.0,
+        "completed"
+    );
+}
+
+pub fn record_cache_key(key: &str) {
+    Span::current().record("cache_key", key);
+}
+
+pub fn record_run_id(run_id: &str) {
+    Span::current().record("run_id", run_id);
+}
+
+pub fn record_bytes(bytes: usize) {
+    Span::current().record("bytes", bytes as u64);
+}
@@ -1,181 +1,653 @@
+// the route filters are deeply nested types
+#![recursion_limit = "256"]
+
+mod admin;
 mod artifact; // actions/upload-artifact@v3 and actions/download-artifact@v3
+mod bundle; // export / import
 mod cache; // actions/cache@v3
+mod cli;
+mod config;
 mod file;
+mod github; // GitHub REST API for cache management (`gh cache`)
+mod health; // liveness and readiness probes
+mod logging;
+mod metrics; // Prometheus
+mod namespace;
+mod shutdown;
+mod ui; // dashboard
+mod upstream; // read-through / write-through to a remote cache server
 mod utils;
+mod version; // cache versions as computed by actions/cache
 
+use crate::admin::*;
 use crate::artifact::*;
 use crate::cache::*;
+use crate::config::{Config, ServeArgs};
+use crate::github::*;
+use crate::health::*;
+use crate::logging::{access_log, request_span, LogFormat};
+use crate::metrics::export_metrics;
+use crate::namespace::*;
+use crate::ui::serve_ui;
+use crate::upstream::*;
+use arc_swap::ArcSwap;
 use clap::Parser;
+use futures_util::{Stream, StreamExt};
+use log::{error, info, warn};
 use once_cell::sync::OnceCell;
-use std::net::Ipv4Addr;
-use warp::Filter;
+use serde_derive::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use warp::http::StatusCode;
+use warp::hyper::body::{Buf, Bytes};
+use warp::{Filter, Rejection, Reply};
 
 #[derive(Parser, Clone, Debug)]
 #[command(version, about = "Local artifact/cache server for use with nektos/act", long_about = None)]
-struct Args {
-    #[clap(short, long, help = "Server address", default_value = "127.0.0.1")]
-    address: Ipv4Addr,
-
-    #[clap(short, long, help = "Server port", default_value = "8000")]
-    port: u16,
+struct Cli {
+    // the server runs without a subcommand, as well as with `serve`
+    #[command(flatten)]
+    serve: ServeArgs,
 
-    #[clap(short, long, help = "Authentication token")]
-    token: Option<String>,
+    #[command(subcommand)]
+    command: Option<cli::Command>,
 }
 
 #[derive(Debug)]
 struct ServerParams {
     host: String, // http://{address}:{port}
     auth: String, // Bearer {token}
+    admin_auth: String,
+    github_auth: String, // token {admin_token}, as sent by the gh command
+    config: Config,
+}
+
+impl ServerParams {
+    fn new(host: String, config: Config) -> ServerParams {
+        // format auth header
+        ServerParams {
+            host,
+            auth: format!("Bearer {}", config.token),
+            admin_auth: format!("Bearer {}", config.admin_token()),
+            github_auth: format!("token {}", config.admin_token()),
+            config,
+        }
+    }
+
+    fn accepts_token(&self, auth: &str) -> bool {
+        auth.eq_ignore_ascii_case(&self.auth)
+    }
+
+    fn accepts_admin_token(&self, auth: &str) -> bool {
+        auth.eq_ignore_ascii_case(&self.admin_auth)
+    }
+
+    fn accepts_github_token(&self, auth: &str) -> bool {
+        self.accepts_admin_token(auth) || auth.eq_ignore_ascii_case(&self.github_auth)
+    }
+}
+
+// swapped as a whole on reload; requests in progress keep the params they have loaded
+static SERVER_PARAMS: OnceCell<ArcSwap<ServerParams>> = OnceCell::new();
+
+fn server_params() -> Arc<ServerParams> {
+    SERVER_PARAMS.get().unwrap().load_full()
+}
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+// checks the Authorization header against the current params, so that rotated tokens apply immediately
+fn authorized(
+    accepts: fn(&ServerParams, &str) -> bool,
+) -> impl Filter<Extract = (), Error = Rejection> + Copy {
+    warp::header::<String>("Authorization")
+        .and_then(move |auth: String| async move {
+            if accepts(&server_params(), &auth) {
+                Ok(())
+            } else {
+                Err(warp::reject::custom(Unauthorized))
+            }
+        })
+        .untuple_one()
 }
 
-static SERVER_PARAMS: OnceCell<ServerParams> = OnceCell::new();
+#[derive(Debug)]
+struct ChunkTooLarge {
+    limit: u64,
+}
+
+impl warp::reject::Reject for ChunkTooLarge {}
+
+// the body of a chunk upload, limited by a size that can be changed by a reload. actions/cache
+// streams chunks without Content-Length, so the limit is enforced while reading the body as well
+fn chunk_body() -> impl Filter<Extract = (Bytes,), Error = Rejection> + Copy {
+    warp::header::optional::<u64>("Content-Length")
+        .and(warp::body::stream())
+        .and_then(read_chunk_body)
+}
+
+async fn read_chunk_body<S, B>(len: Option<u64>, body: S) -> Result<Bytes, Rejection>
+where
+    S: Stream<Item = Result<B, warp::Error>>,
+    B: Buf,
+{
+    let limit = server_params().config.max_chunk_size;
+    if len.is_some_and(|x| x > limit) {
+        return Err(warp::reject::custom(ChunkTooLarge { limit }));
+    }
+
+    let mut buf = Vec::with_capacity(len.unwrap_or(0) as usize);
+    futures_util::pin_mut!(body);
+    while let Some(chunk) = body.next().await {
+        let mut chunk = match chunk {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("[read_chunk_body] failed to read the body: {e}");
+                return Err(warp::reject::reject());
+            }
+        };
+        if (buf.len() + chunk.remaining()) as u64 > limit {
+            return Err(warp::reject::custom(ChunkTooLarge { limit }));
+        }
+        buf.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+    }
+    Ok(Bytes::from(buf))
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct StatusResponse {
+    status: String,
+}
+
+async fn handle_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
+    let (status, code) = if err.find::<Unauthorized>().is_some() {
+        ("unauthorized".to_string(), StatusCode::UNAUTHORIZED)
+    } else if let Some(ChunkTooLarge { limit }) = err.find() {
+        (
+            format!("chunk size exceeds the limit of {limit} bytes"),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        )
+    } else {
+        return Err(err);
+    };
+
+    let res = StatusResponse { status };
+    Ok(warp::reply::with_status(warp::reply::json(&res), code))
+}
+
+fn reload_config(args: &ServeArgs) {
+    let current = server_params();
+    let mut config = match Config::load(args) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("[reload_config] keeping the current config: {e}");
+            return;
+        }
+    };
+
+    // the listener and the log output are set up only once
+    if (config.address, config.port) != (current.config.address, current.config.port) {
+        warn!("[reload_config] address and port are not changed until restart");
+    }
+    if (config.log_format, &config.log_file)
+        != (current.config.log_format, &current.config.log_file)
+    {
+        warn!("[reload_config] log format and file are not changed until restart");
+    }
+    config.address = current.config.address;
+    config.port = current.config.port;
+    config.log_format = current.config.log_format;
+    config.log_file = current.config.log_file.clone();
+
+    let params = ServerParams::new(current.host.clone(), config);
+    SERVER_PARAMS.get().unwrap().store(Arc::new(params));
+    info!("[reload_config] reloaded");
+}
+
+async fn watch_config(args: ServeArgs) {
+    // reload on SIGHUP, or when the config file is modified
+    let modified = |path: &Option<String>| -> Option<SystemTime> {
+        std::fs::metadata(path.as_ref()?).ok()?.modified().ok()
+    };
+    let mut last_modified = modified(&args.config);
+    let mut interval = tokio::time::interval(Duration::from_secs(2));
+
+    #[cfg(unix)]
+    let mut hangup =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).unwrap();
+
+    loop {
+        #[cfg(unix)]
+        let signal = hangup.recv();
+        #[cfg(not(unix))]
+        let signal = std::future::pending::<Option<()>>();
+
+        tokio::select! {
+            _ = signal => {},
+            _ = interval.tick() => {
+                if modified(&args.config) == last_modified {
+                    continue;
+                }
+            },
+        }
+        last_modified = modified(&args.config);
+        reload_config(&args);
+    }
+}
 
 #[tokio::main]
 async fn main() {
-    pretty_env_logger::init();
+    let cli = Cli::parse();
+    let args = match cli.command {
+        None => cli.serve,
+        Some(cli::Command::Serve(args)) => args,
+        Some(command) => {
+            logging::init(LogFormat::Text, None).unwrap();
+            if let Err(e) = cli::run(command) {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+    };
 
-    let args = Args::parse();
-    let token = args.token.unwrap_or_else(|| {
-        std::env::var("ACT_LOCAL_CACHE_SERVER_TOKEN").unwrap_or_else(|_| "token".to_string())
+    let config = Config::load(&args).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        std::process::exit(1);
     });
 
-    // format host and auth header
-    let params = ServerParams {
-        host: format!("http://{}:{}", args.address, args.port),
-        auth: format!("Bearer {token}"),
-    };
-    SERVER_PARAMS.set(params).unwrap();
+    if let Err(e) = logging::init(config.log_format, config.log_file.as_deref()) {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+
+    // format host
+    let host = format!("http://{}:{}", config.address, config.port);
+    let params = ServerParams::new(host, config);
+    SERVER_PARAMS.set(ArcSwap::from_pointee(params)).unwrap();
+    tokio::spawn(watch_config(args));
+
+    // "/ns/<name>/..." selects a namespace; requests without the prefix go to the default one
+    let namespace = warp::path("ns")
+        .and(warp::path::param::<String>())
+        .and_then(|name: String| async move {
+            Namespace::with_prefix(&server_params().host, &name).ok_or_else(warp::reject::not_found)
+        })
+        .or(warp::any().map(|| Namespace::default(&server_params().host)))
+        .unify();
 
     // POST "/<run_id>/artifacts?api-version"
-    let path_get_artifact_upload_url =
-        warp::path!("_apis" / "pipelines" / "workflows" / String / "artifacts")
-            .and(warp::post())
-            .and(warp::header::exact_ignore_case(
-                "Authorization",
-                &SERVER_PARAMS.get().unwrap().auth,
-            ))
-            .and(warp::query::<VersionQuery>())
-            .map(|x, y| get_artifact_upload_url(&SERVER_PARAMS.get().unwrap().host, x, y));
-
-    // GET "/<run_id>/artifacts?api-version"
-    let path_get_artifact_download_url =
-        warp::path!("_apis" / "pipelines" / "workflows" / String / "artifacts")
-            .and(warp::get())
-            .and(warp::header::exact_ignore_case(
-                "Authorization",
-                &SERVER_PARAMS.get().unwrap().auth,
-            ))
-            .and(warp::query::<VersionQuery>())
-            .map(|x, y| get_artifact_download_url(&SERVER_PARAMS.get().unwrap().host, x, y));
-
-    // PATCH "/<run_id>/artifacts?api-version"
-    let path_finalize_artifact =
-        warp::path!("_apis" / "pipelines" / "workflows" / String / "artifacts")
-            .and(warp::patch())
-            .and(warp::header::exact_ignore_case(
-                "Authorization",
-                &SERVER_PARAMS.get().unwrap().auth,
-            ))
-            .and(warp::query::<VersionQuery>())
-            .and(warp::body::content_length_limit(1024))
-            .and(warp::body::json())
-            .map(finalize_artifact);
-
-    // GET "/download/<run_id>"
-    let path_enumerate_artifacts = warp::path::param::<String>()
+    let path_get_artifact_upload_url = namespace
+        .and(warp::path!(
+            "_apis" / "pipelines" / "workflows" / String / "artifacts"
+        ))
+        .and(warp::post())
+        .and(authorized(ServerParams::accepts_token))
+        .and(warp::query::<VersionQuery>())
+        .and(warp::body::content_length_limit(1024))
+        .and(warp::body::json())
+        .map(get_artifact_upload_url);
+
+    // GET "/<run_id>/artifacts?api-version&artifactName"
+    let path_get_artifact_download_url = namespace
+        .and(warp::path!(
+            "_apis" / "pipelines" / "workflows" / String / "artifacts"
+        ))
+        .and(warp::get())
+        .and(authorized(ServerParams::accepts_token))
+        .and(warp::query::<ListArtifactsQuery>())
+        .map(get_artifact_download_url);
+
+    // PATCH "/<run_id>/artifacts?api-version&artifactName"
+    let path_finalize_artifact = namespace
+        .and(warp::path!(
+            "_apis" / "pipelines" / "workflows" / String / "artifacts"
+        ))
+        .and(warp::patch())
+        .and(authorized(ServerParams::accepts_token))
+        .and(warp::query::<FinalizeArtifactQuery>())
+        .and(warp::body::content_length_limit(1024))
+        .and(warp::body::json())
+        .map(|ns, run_id, query, input| {
+            let compression = server_params().config.compression;
+            finalize_artifact(ns, compression, run_id, query, input)
+        });
+
+    // GET "/download/..."
+    let path_download = namespace
+        .and(warp::path("download"))
+        .and(warp::get())
+        .and(authorized(ServerParams::accepts_token));
+
+    // GET "/download/<run_id>?itemPath"
+    let path_enumerate_artifacts = path_download
+        .and(warp::path::param::<String>())
         .and(warp::path::end())
-        .map(|x| enumerate_artifacts(&SERVER_PARAMS.get().unwrap().host, x));
+        .and(warp::query::<ItemPathFilter>())
+        .map(enumerate_artifacts);
 
     // GET "/download/<run_id>/<path>"
-    let path_download_artifact = warp::path::param::<String>()
+    let path_download_artifact = path_download
+        .and(warp::path::param::<String>())
         .and(warp::path::tail())
         .and(warp::header::optional::<String>("Content-Range"))
+        .and(warp::header::optional::<String>("Accept-Encoding"))
         .map(download_artifact);
 
     // either of two above
-    let path_download_or_enumerate_artifact = warp::path("download")
+    let path_download_or_enumerate_artifact = path_enumerate_artifacts.or(path_download_artifact);
+
+    // GET "/artifacts/<run_id>/<name>?itemPath"
+    let path_enumerate_artifact_items = namespace
+        .and(warp::path!("artifacts" / String / String))
         .and(warp::get())
-        .and(warp::header::exact_ignore_case(
-            "Authorization",
-            &SERVER_PARAMS.get().unwrap().auth,
-        ))
-        .and(path_enumerate_artifacts.or(path_download_artifact));
+        .and(authorized(ServerParams::accepts_token))
+        .and(warp::query::<ItemPathFilter>())
+        .map(enumerate_artifact_items);
+
+    // GET "/artifacts/<run_id>/<name>/zip"
+    let path_download_artifact_zip = namespace
+        .and(warp::path!("artifacts" / String / String / "zip"))
+        .and(warp::get())
+        .and(authorized(ServerParams::accepts_token))
+        .map(download_artifact_zip);
 
     // PUT "/upload/<run_id>"
-    let path_upload_artifact = warp::path!("upload" / String)
+    let path_upload_artifact = namespace
+        .and(warp::path!("upload" / String))
         .and(warp::put())
         .and(warp::query::<ItemPathQuery>())
         .and(warp::header::optional::<String>("Content-Encoding"))
         .and(warp::header::optional::<String>("Content-Range"))
-        .and(warp::body::content_length_limit(64 * 1024 * 1024))
-        .and(warp::body::bytes())
+        .and(chunk_body())
         .map(upload_artifact);
 
     // POST _apis/artifactcache/caches/
-    let path_reserve_cache = warp::path!("_apis" / "artifactcache" / "caches")
+    let path_reserve_cache = namespace
+        .and(warp::path!("_apis" / "artifactcache" / "caches"))
         .and(warp::post())
-        .and(warp::header::exact_ignore_case(
-            "Authorization",
-            &SERVER_PARAMS.get().unwrap().auth,
-        ))
+        .and(authorized(ServerParams::accepts_token))
         .and(warp::body::content_length_limit(1024))
         .and(warp::body::json())
-        .map(reserve_cache);
+        .map(|ns: Namespace, x| {
+            let config = &server_params().config;
+            let quota = config.quota(&ns.name);
+            reserve_cache(ns, quota, config.max_cache_size, config.overwrite, x)
+        });
 
     // PATCH _apis/artifactcache/caches/:cacheId
-    let path_upload_cache = warp::path!("_apis" / "artifactcache" / "caches" / String / String)
-        .and(warp::patch())
-        .and(warp::header::exact_ignore_case(
-            "Authorization",
-            &SERVER_PARAMS.get().unwrap().auth,
+    let path_upload_cache = namespace
+        .and(warp::path!(
+            "_apis" / "artifactcache" / "caches" / String / String
         ))
+        .and(warp::patch())
+        .and(authorized(ServerParams::accepts_token))
         .and(warp::header::optional::<String>("Content-Encoding"))
         .and(warp::header::optional::<String>("Content-Range"))
-        .and(warp::body::bytes())
+        .and(chunk_body())
         .map(upload_cache);
 
     // POST _apis/artifactcache/caches/:cacheId body = { size: filesize }
-    let path_finalize_cache = warp::path!("_apis" / "artifactcache" / "caches" / String / String)
-        .and(warp::post())
-        .and(warp::header::exact_ignore_case(
-            "Authorization",
-            &SERVER_PARAMS.get().unwrap().auth,
+    let path_finalize_cache = namespace
+        .and(warp::path!(
+            "_apis" / "artifactcache" / "caches" / String / String
         ))
+        .and(warp::post())
+        .and(authorized(ServerParams::accepts_token))
         .and(warp::body::content_length_limit(1024))
         .and(warp::body::json())
-        .map(finalize_cache);
+        .map(|ns: Namespace, key: String, version: String, input| {
+            let config = &server_params().config;
+            let res = finalize_cache(
+                ns.clone(),
+                config.compression,
+                config.overwrite,
+                key.clone(),
+                version.clone(),
+                input,
+            )
+            .into_response();
+
+            match Upstream::from_config(config) {
+                Some(upstream) if upstream.write_through && res.status() == StatusCode::OK => {
+                    tokio::spawn(push_cache(ns, upstream, key, version));
+                }
+                _ => {}
+            }
+            res
+        });
 
     // GET _apis/artifactcache/cache?keys=${encodeURIComponent(keys.join(','))}&version=${version}`;
     // -> { archiveLocation }
-    let path_enumerate_cache = warp::path!("_apis" / "artifactcache" / "cache")
+    let path_enumerate_cache = namespace
+        .and(warp::path!("_apis" / "artifactcache" / "cache"))
         .and(warp::get())
-        .and(warp::header::exact_ignore_case(
-            "Authorization",
-            &SERVER_PARAMS.get().unwrap().auth,
-        ))
+        .and(authorized(ServerParams::accepts_token))
         .and(warp::query::<EnumerateQuery>())
-        .map(|x| enumerate_caches(&SERVER_PARAMS.get().unwrap().host, x));
+        .then(|ns, query| {
+            let config = &server_params().config;
+            let upstream = Upstream::from_config(config);
+            enumerate_caches_with_upstream(ns, upstream, config.compatible_versions, query)
+        });
 
     // GET _apis/artifactcache/cache/:cacheId
-    let path_download_cache =
-        warp::path!("_apis" / "artifactcache" / "cache" / "download" / String / String)
-            .and(warp::get())
-            .and(warp::header::optional::<String>("Content-Range"))
-            .map(download_cache);
+    let path_download_cache = namespace
+        .and(warp::path!(
+            "_apis" / "artifactcache" / "cache" / "download" / String / String
+        ))
+        .and(warp::get())
+        .and(warp::header::optional::<String>("Content-Range"))
+        .and(warp::header::optional::<String>("Accept-Encoding"))
+        .then(|ns, key, version, range, accept_encoding| {
+            let config = &server_params().config;
+            let upstream = Upstream::from_config(config);
+            download_cache_with_upstream(
+                ns,
+                upstream,
+                config.compression,
+                key,
+                version,
+                range,
+                accept_encoding,
+            )
+        });
+
+    // GET /namespaces
+    let path_enumerate_namespaces = warp::path!("namespaces")
+        .and(warp::get())
+        .and(authorized(ServerParams::accepts_token))
+        .map(enumerate_namespaces);
+
+    // /admin/...; the token is checked after the path and the method, so that requests to other
+    // routes are not answered with 401 when the admin token differs
+    let path_admin = namespace.and(warp::path("admin"));
+
+    // GET /admin/caches?prefix=
+    let path_admin_list_caches = path_admin
+        .and(warp::path!("caches"))
+        .and(warp::get())
+        .and(authorized(ServerParams::accepts_admin_token))
+        .and(warp::query::<PrefixQuery>())
+        .map(admin_list_caches);
+
+    // GET /admin/caches/<key>?version=
+    let path_admin_inspect_cache = path_admin
+        .and(warp::path!("caches" / String))
+        .and(warp::get())
+        .and(authorized(ServerParams::accepts_admin_token))
+        .and(warp::query::<CacheVersionQuery>())
+        .map(admin_inspect_cache);
+
+    // DELETE /admin/caches/<key>?version=
+    let path_admin_delete_cache = path_admin
+        .and(warp::path!("caches" / String))
+        .and(warp::delete())
+        .and(authorized(ServerParams::accepts_admin_token))
+        .and(warp::query::<CacheVersionQuery>())
+        .map(admin_delete_cache);
+
+    // DELETE /admin/caches?prefix=
+    let path_admin_delete_caches = path_admin
+        .and(warp::path!("caches"))
+        .and(warp::delete())
+        .and(authorized(ServerParams::accepts_admin_token))
+        .and(warp::query::<PrefixQuery>())
+        .map(admin_delete_caches);
+
+    // POST /admin/version
+    let path_admin_cache_version = path_admin
+        .and(warp::path!("version"))
+        .and(warp::post())
+        .and(authorized(ServerParams::accepts_admin_token))
+        .and(warp::body::content_length_limit(64 * 1024))
+        .and(warp::body::json())
+        .map(admin_cache_version);
+
+    // "/api/v3/repos/..." is what gh requests to hosts other than github.com
+    let path_github = namespace.and(
+        warp::path("api")
+            .and(warp::path("v3"))
+            .or(warp::any())
+            .unify(),
+    );
+
+    // GET /repos/<owner>/<repo>/actions/caches
+    let path_github_list_caches = path_github
+        .and(warp::path!(
+            "repos" / String / String / "actions" / "caches"
+        ))
+        .and(warp::get())
+        .and(authorized(ServerParams::accepts_github_token))
+        .and(warp::query::<ListCachesQuery>())
+        .map(github_list_caches);
+
+    // DELETE /repos/<owner>/<repo>/actions/caches?key=
+    let path_github_delete_caches_by_key = path_github
+        .and(warp::path!(
+            "repos" / String / String / "actions" / "caches"
+        ))
+        .and(warp::delete())
+        .and(authorized(ServerParams::accepts_github_token))
+        .and(warp::query::<DeleteCachesQuery>())
+        .map(github_delete_caches_by_key);
+
+    // DELETE /repos/<owner>/<repo>/actions/caches/<cache_id>
+    let path_github_delete_cache_by_id = path_github
+        .and(warp::path!(
+            "repos" / String / String / "actions" / "caches" / u64
+        ))
+        .and(warp::delete())
+        .and(authorized(ServerParams::accepts_github_token))
+        .map(github_delete_cache_by_id);
+
+    // GET /repos/<owner>/<repo>/actions/cache/usage
+    let path_github_cache_usage = path_github
+        .and(warp::path!(
+            "repos" / String / String / "actions" / "cache" / "usage"
+        ))
+        .and(warp::get())
+        .and(authorized(ServerParams::accepts_github_token))
+        .map(github_cache_usage);
+
+    // GET /admin/artifacts
+    let path_admin_list_runs = path_admin
+        .and(warp::path!("artifacts"))
+        .and(warp::get())
+        .and(authorized(ServerParams::accepts_admin_token))
+        .map(admin_list_runs);
+
+    // GET /admin/artifacts/<run_id>
+    let path_admin_list_artifacts = path_admin
+        .and(warp::path!("artifacts" / String))
+        .and(warp::get())
+        .and(authorized(ServerParams::accepts_admin_token))
+        .map(admin_list_artifacts);
+
+    // DELETE /admin/artifacts/<run_id>
+    let path_admin_delete_run = path_admin
+        .and(warp::path!("artifacts" / String))
+        .and(warp::delete())
+        .and(authorized(ServerParams::accepts_admin_token))
+        .map(admin_delete_run);
+
+    // GET /admin/artifacts/<run_id>/<name>/zip
+    let path_admin_download_artifact = path_admin
+        .and(warp::path!("artifacts" / String / String / "zip"))
+        .and(warp::get())
+        .and(authorized(ServerParams::accepts_admin_token))
+        .map(admin_download_artifact);
+
+    // GET /ui/..., without the token; the pages ask for the admin token to call the admin API
+    let path_ui = warp::path("ui")
+        .and(warp::path::tail())
+        .and(warp::get())
+        .map(|tail: warp::path::Tail| serve_ui(tail.as_str()));
+
+    // GET /metrics
+    let path_metrics = warp::path!("metrics").and(warp::get()).map(export_metrics);
+
+    // GET /healthz, /readyz and /version, without the token for probes
+    let path_healthz = warp::path!("healthz").and(warp::get()).map(check_liveness);
+
+    let path_readyz = warp::path!("readyz")
+        .and(warp::get())
+        .map(|| check_readiness(server_params().config.min_free_space));
+
+    let path_version = warp::path!("version").and(warp::get()).map(show_version);
 
     let routes = warp::any().and(
         path_get_artifact_upload_url
             .or(path_get_artifact_download_url)
             .or(path_finalize_artifact)
             .or(path_download_or_enumerate_artifact)
+            .or(path_enumerate_artifact_items)
+            .or(path_download_artifact_zip)
             .or(path_upload_artifact)
             .or(path_reserve_cache)
             .or(path_upload_cache)
             .or(path_finalize_cache)
             .or(path_enumerate_cache)
-            .or(path_download_cache),
+            .or(path_download_cache)
+            .or(path_enumerate_namespaces)
+            .or(path_admin_list_caches)
+            .or(path_admin_inspect_cache)
+            .or(path_admin_delete_cache)
+            .or(path_admin_delete_caches)
+            .or(path_admin_cache_version)
+            .or(path_admin_list_runs)
+            .or(path_admin_list_artifacts)
+            .or(path_admin_delete_run)
+            .or(path_admin_download_artifact)
+            .or(path_ui)
+            .or(path_metrics)
+            .or(path_healthz)
+            .or(path_readyz)
+            .or(path_version)
+            .or(path_github_list_caches)
+            .or(path_github_delete_caches_by_key)
+            .or(path_github_delete_cache_by_id)
+            .or(path_github_cache_usage),
     );
 
-    warp::serve(routes).run((args.address, args.port)).await;
+    // a span per request correlates the handler logs (and chunks of an upload, by cache key or run id)
+    let routes = routes
+        .recover(handle_rejection)
+        .with(warp::log::custom(access_log))
+        .with(warp::trace(request_span));
+
+    // on SIGTERM or SIGINT, refuse new reservations and let reserved uploads finish before closing
+    // the listener; in-progress requests are completed by the graceful shutdown of warp
+    let params = server_params();
+    let (_, server) = warp::serve(routes).bind_with_graceful_shutdown(
+        (params.config.address, params.config.port),
+        async move {
+            shutdown::wait_for_signal().await;
+            let timeout = server_params().config.shutdown_timeout;
+            shutdown::drain(Duration::from_secs(timeout)).await;
+        },
+    );
+    server.await;
 }
@@ -0,0 +1,103 @@
+use crate::file::{count_pending_uploads, disk_usage};
+use crate::namespace::list_namespaces;
+use log::info;
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    Encoder, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+use warp::http::Response;
+
+pub static CACHE_LOOKUPS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "act_cache_lookups_total",
+        "Cache lookups by result (hit, compatible or miss)",
+        &["result"]
+    )
+    .unwrap()
+});
+
+pub static UPLOADED_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "act_uploaded_bytes_total",
+        "Bytes received from clients",
+        &["route"]
+    )
+    .unwrap()
+});
+
+pub static DOWNLOADED_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "act_downloaded_bytes_total",
+        "Bytes sent to clients",
+        &["route"]
+    )
+    .unwrap()
+});
+
+pub static FINALIZE_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "act_finalize_duration_seconds",
+        "Time to concatenate uploaded chunks",
+        &["kind"],
+        vec![0.001, 0.01, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0]
+    )
+    .unwrap()
+});
+
+pub static EVICTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "act_evictions_total",
+        "Caches and artifact runs removed",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+static STORAGE_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "act_storage_bytes",
+        "Bytes stored in the data directory",
+        &["namespace", "kind"]
+    )
+    .unwrap()
+});
+
+static UPLOADS_IN_FLIGHT: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "act_uploads_in_flight",
+        "Uploads that have chunks not finalized yet"
+    )
+    .unwrap()
+});
+
+// GET /metrics
+pub fn export_metrics() -> Response<String> {
+    info!("[export_metrics]");
+
+    // gauges derived from the data directory are refreshed at scrape time
+    let mut pending = 0;
+    for ns in list_namespaces() {
+        for (kind, dir) in [("caches", ns.caches()), ("artifacts", ns.artifacts())] {
+            let (_, size) = disk_usage(&dir);
+            STORAGE_BYTES
+                .with_label_values(&[ns.name.as_str(), kind])
+                .set(size as i64);
+
+            pending += count_pending_uploads(&dir);
+        }
+    }
+    UPLOADS_IN_FLIGHT.set(pending as i64);
+
+    let encoder = TextEncoder::new();
+    let mut buf = Vec::new();
+    encoder.encode(&prometheus::gather(), &mut buf).unwrap();
+
+    let len = buf.len();
+    info!("[export_metrics] response = <{len} bytes>");
+
+    Response::builder()
+        .header("Content-Type", encoder.format_type())
+        .body(String::from_utf8(buf).unwrap())
+        .unwrap()
+}
@@ -0,0 +1,136 @@
+use crate::file::disk_usage;
+use crate::utils::glob_in;
+use log::info;
+use serde_derive::Serialize;
+use std::path::Path;
+use warp::http::StatusCode;
+use warp::reply::{json, with_status, Json, WithStatus};
+
+pub const DATA_DIR: &str = ".act_local_cache";
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+// a namespace partitions caches and artifacts of one repository (or any other unit) from others.
+// it is selected by the "/ns/<name>/" URL prefix, and the prefix is kept in the URLs the server
+// hands out so that the subsequent upload / download requests stay in the same namespace.
+#[derive(Clone, Debug)]
+pub struct Namespace {
+    pub name: String,
+    pub host: String, // http://{address}:{port}[/ns/{name}]
+    pub root: String, // .act_local_cache[/namespaces/{name}]
+}
+
+impl Namespace {
+    pub fn default(host: &str) -> Namespace {
+        Namespace {
+            name: DEFAULT_NAMESPACE.to_string(),
+            host: host.to_string(),
+            root: DATA_DIR.to_string(),
+        }
+    }
+
+    pub fn with_prefix(host: &str, name: &str) -> Option<Namespace> {
+        let mut ns = Namespace::open(name)?;
+        ns.host = format!("{host}/ns/{name}");
+        Some(ns)
+    }
+
+    pub fn open(name: &str) -> Option<Namespace> {
+        if !is_valid_name(name) {
+            return None;
+        }
+
+        let root = if name == DEFAULT_NAMESPACE {
+            DATA_DIR.to_string()
+        } else {
+            format!("{DATA_DIR}/namespaces/{name}")
+        };
+
+        Some(Namespace {
+            name: name.to_string(),
+            host: String::new(),
+            root,
+        })
+    }
+
+    pub fn caches(&self) -> String {
+        format!("{}/caches", self.root)
+    }
+
+    pub fn artifacts(&self) -> String {
+        format!("{}/artifacts", self.root)
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    // the name becomes a directory name; reject anything that can escape the data directory
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+pub fn list_namespaces() -> Vec<Namespace> {
+    let mut array = vec![Namespace::open(DEFAULT_NAMESPACE).unwrap()];
+
+    let dir = format!("{DATA_DIR}/namespaces");
+    for path in glob_in(&dir, "*").unwrap_or_default() {
+        let name = path.to_str().unwrap();
+        if !Path::new(&format!("{dir}/{name}")).is_dir() {
+            continue;
+        }
+        if let Some(ns) = Namespace::open(name) {
+            array.push(ns);
+        }
+    }
+    array
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct UsageElement {
+    count: usize,
+    size: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct NamespaceElement {
+    name: String,
+    caches: UsageElement,
+    artifacts: UsageElement,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct NamespaceArrayResponse {
+    status: String,
+    count: usize,
+    value: Vec<NamespaceElement>,
+}
+
+pub fn enumerate_namespaces() -> WithStatus<Json> {
+    info!("[enumerate_namespaces]");
+
+    let mut array = Vec::new();
+    for ns in list_namespaces() {
+        let (count, size) = disk_usage(&ns.caches());
+        let caches = UsageElement { count, size };
+
+        let (count, size) = disk_usage(&ns.artifacts());
+        let artifacts = UsageElement { count, size };
+
+        array.push(NamespaceElement {
+            name: ns.name,
+            caches,
+            artifacts,
+        });
+    }
+
+    let count = array.len();
+    let res = NamespaceArrayResponse {
+        status: "success".to_string(),
+        count,
+        value: array,
+    };
+    info!("[enumerate_namespaces] response = {res:?}");
+
+    with_status(json(&res), StatusCode::OK)
+}
@@ -0,0 +1,118 @@
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// set once a signal is received; new reservations are refused from then on
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+// uploads not receiving chunks for this long are considered abandoned, e.g. by a cancelled job
+const UPLOAD_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug)]
+struct PendingUpload {
+    count: usize,
+    last_active: Instant,
+}
+
+// uploads reserved but not finalized yet, as "{kind}:{namespace}/{id}" -> count
+static PENDING_UPLOADS: Lazy<Mutex<HashMap<String, PendingUpload>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn is_draining() -> bool {
+    DRAINING.load(Ordering::SeqCst)
+}
+
+fn expire_idle_uploads(pending: &mut HashMap<String, PendingUpload>) {
+    pending.retain(|id, x| {
+        let active = x.last_active.elapsed() < UPLOAD_IDLE_TIMEOUT;
+        if !active {
+            warn!("[expire_idle_uploads] {id} has not been finalized; given up");
+        }
+        active
+    });
+}
+
+pub fn begin_upload(id: &str) {
+    let mut pending = PENDING_UPLOADS.lock().unwrap();
+    expire_idle_uploads(&mut pending);
+
+    let upload = pending.entry(id.to_string()).or_insert(PendingUpload {
+        count: 0,
+        last_active: Instant::now(),
+    });
+    upload.count += 1;
+    upload.last_active = Instant::now();
+}
+
+pub fn touch_upload(id: &str) {
+    // on each chunk; keeps the upload from being expired
+    if let Some(upload) = PENDING_UPLOADS.lock().unwrap().get_mut(id) {
+        upload.last_active = Instant::now();
+    }
+}
+
+pub fn end_upload(id: &str) {
+    let mut pending = PENDING_UPLOADS.lock().unwrap();
+    if let Some(upload) = pending.get_mut(id) {
+        upload.count -= 1;
+        if upload.count == 0 {
+            pending.remove(id);
+        }
+    }
+}
+
+pub fn is_upload_pending(id: &str) -> bool {
+    // reserved, and receiving chunks
+    let mut pending = PENDING_UPLOADS.lock().unwrap();
+    expire_idle_uploads(&mut pending);
+    pending.contains_key(id)
+}
+
+fn count_pending() -> usize {
+    let mut pending = PENDING_UPLOADS.lock().unwrap();
+    expire_idle_uploads(&mut pending);
+    pending.values().map(|x| x.count).sum()
+}
+
+pub async fn wait_for_signal() {
+    let ctrl_c = tokio::signal::ctrl_c();
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut term = signal(SignalKind::terminate()).unwrap();
+        tokio::select! {
+            _ = ctrl_c => {},
+            _ = term.recv() => {},
+        }
+    }
+
+    #[cfg(not(unix))]
+    ctrl_c.await.unwrap();
+}
+
+pub async fn drain(timeout: Duration) {
+    // keep serving chunks and finalizes of the uploads already reserved, until they complete or time out
+    DRAINING.store(true, Ordering::SeqCst);
+    info!(
+        "[drain] shutting down; waiting for {} uploads",
+        count_pending()
+    );
+
+    let wait = async {
+        while count_pending() > 0 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    };
+    if tokio::time::timeout(timeout, wait).await.is_err() {
+        let pending = PENDING_UPLOADS.lock().unwrap();
+        warn!(
+            "[drain] timed out; {} uploads left unfinished: {:?}",
+            pending.values().map(|x| x.count).sum::<usize>(),
+            pending.keys().collect::<Vec<_>>()
+        );
+    }
+}
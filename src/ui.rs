@@ -0,0 +1,40 @@
+use log::info;
+use warp::http::{Response, StatusCode};
+
+// the dashboard; static files compiled into the binary, which call the admin API from the browser
+const ASSETS: &[(&str, &str, &str)] = &[
+    (
+        "index.html",
+        "text/html; charset=utf-8",
+        include_str!("ui/index.html"),
+    ),
+    (
+        "app.js",
+        "text/javascript; charset=utf-8",
+        include_str!("ui/app.js"),
+    ),
+    (
+        "style.css",
+        "text/css; charset=utf-8",
+        include_str!("ui/style.css"),
+    ),
+];
+
+// GET /ui/<name>
+pub fn serve_ui(name: &str) -> Response<Vec<u8>> {
+    info!("[serve_ui] name = {name}");
+
+    let name = if name.is_empty() { "index.html" } else { name };
+    let Some((_, content_type, body)) = ASSETS.iter().find(|(x, _, _)| *x == name) else {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Vec::new())
+            .unwrap();
+    };
+
+    Response::builder()
+        .header("Content-Type", *content_type)
+        .header("Cache-Control", "no-cache")
+        .body(body.as_bytes().to_vec())
+        .unwrap()
+}
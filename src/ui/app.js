@@ -0,0 +1,226 @@
+// the dashboard; everything goes through the admin API with the token given in the page
+"use strict";
+
+const $ = (id) => document.getElementById(id);
+
+function base() {
+  const ns = $("namespace").value.trim() || "default";
+  return ns === "default" ? "" : `/ns/${encodeURIComponent(ns)}`;
+}
+
+function showMessage(text) {
+  $("message").textContent = text;
+  $("message").hidden = !text;
+}
+
+async function request(method, path, namespaced = true) {
+  const res = await fetch((namespaced ? base() : "") + path, {
+    method,
+    headers: { Authorization: `Bearer ${$("token").value}` },
+  });
+  if (res.status === 401) {
+    throw new Error("unauthorized; check the admin token");
+  }
+  return res;
+}
+
+async function requestJson(method, path) {
+  const res = await request(method, path);
+  const body = await res.json();
+  if (!res.ok && res.status !== 404) {
+    throw new Error(`${method} ${path}: ${body.status}`);
+  }
+  return body;
+}
+
+function formatSize(bytes) {
+  const units = ["B", "KiB", "MiB", "GiB", "TiB"];
+  let i = 0;
+  while (bytes >= 1024 && i < units.length - 1) {
+    bytes /= 1024;
+    i++;
+  }
+  return `${i === 0 ? bytes : bytes.toFixed(1)} ${units[i]}`;
+}
+
+function cell(row, content, className) {
+  const td = row.insertCell();
+  if (content instanceof Node) {
+    td.append(content);
+  } else {
+    td.textContent = content;
+  }
+  if (className) {
+    td.className = className;
+  }
+  return td;
+}
+
+function button(label, onclick, className) {
+  const x = document.createElement("button");
+  x.type = "button";
+  x.textContent = label;
+  x.onclick = () => onclick().catch((e) => showMessage(e.message));
+  if (className) {
+    x.className = className;
+  }
+  return x;
+}
+
+async function loadNamespaces() {
+  // /namespaces takes the server token, which may differ from the admin token
+  const res = await request("GET", "/namespaces", false).catch(() => null);
+  if (!res || !res.ok) {
+    return;
+  }
+  const list = $("namespaces");
+  list.replaceChildren();
+  for (const ns of (await res.json()).value) {
+    const option = document.createElement("option");
+    option.value = ns.name;
+    list.append(option);
+  }
+}
+
+async function loadCaches() {
+  const prefix = encodeURIComponent($("prefix").value);
+  const body = await requestJson("GET", `/admin/caches?prefix=${prefix}`);
+  $("cache-summary").textContent = `${body.count} caches, ${formatSize(body.size)}`;
+
+  const tbody = $("caches");
+  tbody.replaceChildren();
+  for (const cache of body.value) {
+    const row = tbody.insertRow();
+    cell(row, cache.key, "key");
+
+    const version = document.createElement("code");
+    version.textContent = cache.version.slice(0, 16);
+    version.title = cache.version;
+    cell(row, version);
+
+    cell(row, formatSize(cache.size), "number");
+    cell(row, cache.hits, "number");
+    cell(row, cache.createdAt);
+    cell(row, cache.lastAccessedAt);
+    cell(row, button("Delete", () => deleteCache(cache), "danger"));
+  }
+}
+
+async function deleteCache(cache) {
+  if (!confirm(`Delete cache ${cache.key} (${cache.version})?`)) {
+    return;
+  }
+  // keys are stored as they appeared in the upload URLs, i.e. already encoded
+  const version = encodeURIComponent(cache.version);
+  await requestJson("DELETE", `/admin/caches/${cache.key}?version=${version}`);
+  await loadCaches();
+}
+
+async function loadRuns() {
+  const body = await requestJson("GET", "/admin/artifacts");
+
+  const tbody = $("runs");
+  tbody.replaceChildren();
+  $("artifacts").replaceChildren();
+  for (const run of body.value) {
+    const row = tbody.insertRow();
+    cell(row, button(run.runId, () => loadArtifacts(run.runId)));
+    cell(row, run.count, "number");
+    cell(row, formatSize(run.size), "number");
+    cell(row, button("Delete", () => deleteRun(run.runId), "danger"));
+  }
+}
+
+async function deleteRun(runId) {
+  if (!confirm(`Delete all artifacts of run ${runId}?`)) {
+    return;
+  }
+  await requestJson("DELETE", `/admin/artifacts/${encodeURIComponent(runId)}`);
+  await loadRuns();
+}
+
+function fileTree(files) {
+  // "a/b/c.txt" -> nested lists
+  const root = {};
+  for (const file of files) {
+    let node = root;
+    for (const part of file.split("/")) {
+      node = node[part] ??= {};
+    }
+  }
+
+  const render = (node) => {
+    const ul = document.createElement("ul");
+    for (const name of Object.keys(node).sort()) {
+      const li = document.createElement("li");
+      const children = Object.keys(node[name]).length > 0;
+      li.textContent = children ? `${name}/` : name;
+      if (children) {
+        li.append(render(node[name]));
+      }
+      ul.append(li);
+    }
+    return ul;
+  };
+  return render(root);
+}
+
+async function loadArtifacts(runId) {
+  const body = await requestJson("GET", `/admin/artifacts/${encodeURIComponent(runId)}`);
+
+  const div = $("artifacts");
+  div.replaceChildren();
+  const title = document.createElement("h3");
+  title.textContent = `Run ${runId}`;
+  div.append(title);
+
+  for (const artifact of body.value ?? []) {
+    const details = document.createElement("details");
+    details.className = "artifact";
+
+    const summary = document.createElement("summary");
+    summary.textContent = `${artifact.name} (${artifact.fileCount} files, ${formatSize(artifact.size)}, ${artifact.createdAt}) `;
+    summary.append(button("Download", () => downloadArtifact(runId, artifact.name)));
+    details.append(summary, fileTree(artifact.files));
+    div.append(details);
+  }
+}
+
+async function downloadArtifact(runId, name) {
+  // fetched with the token, then saved from memory
+  const path = `/admin/artifacts/${encodeURIComponent(runId)}/${encodeURIComponent(name)}/zip`;
+  const res = await request("GET", path);
+  if (!res.ok) {
+    throw new Error(`GET ${path}: ${res.status}`);
+  }
+
+  const a = document.createElement("a");
+  a.href = URL.createObjectURL(await res.blob());
+  a.download = `${name}.zip`;
+  a.click();
+  setTimeout(() => URL.revokeObjectURL(a.href), 60000);
+}
+
+async function loadAll() {
+  showMessage("");
+  localStorage.setItem("token", $("token").value);
+  localStorage.setItem("namespace", $("namespace").value);
+  await loadNamespaces();
+  await Promise.all([loadCaches(), loadRuns()]);
+}
+
+function onSubmit(f) {
+  return (e) => {
+    e.preventDefault();
+    f().catch((e) => showMessage(e.message));
+  };
+}
+
+$("settings").onsubmit = onSubmit(loadAll);
+$("cache-filter").onsubmit = onSubmit(loadCaches);
+
+$("token").value = localStorage.getItem("token") ?? "";
+$("namespace").value = localStorage.getItem("namespace") ?? "default";
+if ($("token").value) {
+  loadAll().catch((e) => showMessage(e.message));
+}
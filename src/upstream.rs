@@ -0,0 +1,408 @@
+use crate::cache::{cache_found, download_cache, enumerate_caches, EnumerateQuery};
+use crate::config::Config;
+use crate::file::{
+    finalize_files, is_path_component, load_metadata, open_contents, remove_empty_dirs, Compression,
+};
+use crate::namespace::Namespace;
+use crate::utils::status_response;
+use futures_util::StreamExt;
+use log::{error, info};
+use once_cell::sync::Lazy;
+use serde_derive::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use warp::http::StatusCode;
+use warp::hyper::body::{Bytes, Sender};
+use warp::hyper::Body;
+use warp::reply::Response;
+use warp::Reply;
+
+// a team-wide server that caches missing locally are looked up from (read-through), and optionally
+// uploaded to (write-through). it is either another act-local-cache-server or a server implementing
+// the cache protocol of GitHub Actions.
+#[derive(Clone, Debug)]
+pub struct Upstream {
+    url: String,
+    token: Option<String>,
+    pub write_through: bool,
+}
+
+impl Upstream {
+    pub fn from_config(config: &Config) -> Option<Upstream> {
+        Some(Upstream {
+            url: config.upstream.clone()?,
+            token: config.upstream_token.clone(),
+            write_through: config.upstream_write_through,
+        })
+    }
+
+    fn base_url(&self, ns: &Namespace) -> String {
+        // namespaces map to the same namespaces of the upstream
+        if ns.name == crate::namespace::DEFAULT_NAMESPACE {
+            self.url.clone()
+        } else {
+            format!("{}/ns/{}", self.url, ns.name)
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        // the token is not sent to other hosts, e.g. a blob storage the archive is located at
+        let req = CLIENT.request(method, url);
+        match &self.token {
+            Some(token) if url.starts_with(&self.url) => req.bearer_auth(token),
+            _ => req,
+        }
+    }
+}
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+// archive locations of the caches found upstream, by local path, until downloaded. lookups are not
+// always followed by a download, so they are forgotten after a while, or when too many
+const LOCATION_TTL: Duration = Duration::from_secs(10 * 60);
+const MAX_LOCATIONS: usize = 1024;
+
+static LOCATIONS: Lazy<Mutex<HashMap<String, (String, Instant)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn remember_location(path: String, url: String) {
+    let mut locations = LOCATIONS.lock().unwrap();
+    locations.retain(|_, (_, found_at)| found_at.elapsed() < LOCATION_TTL);
+    if locations.len() >= MAX_LOCATIONS {
+        let oldest = locations
+            .iter()
+            .min_by_key(|(_, (_, found_at))| *found_at)
+            .map(|(x, _)| x.clone());
+        if let Some(oldest) = oldest {
+            locations.remove(&oldest);
+        }
+    }
+    locations.insert(path, (url, Instant::now()));
+}
+
+fn take_location(path: &str) -> Option<String> {
+    let (url, found_at) = LOCATIONS.lock().unwrap().remove(path)?;
+    (found_at.elapsed() < LOCATION_TTL).then_some(url)
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct UpstreamCache {
+    #[serde(rename = "archiveLocation")]
+    url: String,
+
+    #[serde(rename = "cacheKey")]
+    key: String,
+}
+
+// GET _apis/artifactcache/cache; looks up the upstream on a miss
+pub async fn enumerate_caches_with_upstream(
+    ns: Namespace,
+    upstream: Option<Upstream>,
+    compatible: bool,
+    query: EnumerateQuery,
+) -> Response {
+    let res = enumerate_caches(ns.clone(), compatible, query.clone()).into_response();
+    let Some(upstream) = upstream else {
+        return res;
+    };
+    if res.status() != StatusCode::NOT_FOUND {
+        return res;
+    }
+
+    let url = format!("{}/_apis/artifactcache/cache", upstream.base_url(&ns));
+    let req = upstream
+        .request(reqwest::Method::GET, &url)
+        .query(&[("keys", &query.keys), ("version", &query.version)]);
+
+    let found = match req.send().await {
+        Ok(x) if x.status() == reqwest::StatusCode::OK => x.json::<UpstreamCache>().await.ok(),
+        Ok(x) => {
+            info!(
+                "[enumerate_caches_with_upstream] upstream response = {}",
+                x.status()
+            );
+            None
+        }
+        Err(e) => {
+            error!("[enumerate_caches_with_upstream] upstream unavailable: {e}");
+            None
+        }
+    };
+    let Some(found) = found else {
+        return res;
+    };
+    info!("[enumerate_caches_with_upstream] found upstream: {found:?}");
+
+    // saved locally under the key it was found by
+    if !is_path_component(&found.key) {
+        error!("[enumerate_caches_with_upstream] invalid key from upstream: {found:?}");
+        return res;
+    }
+
+    // the client downloads it from this server, which saves it on the way
+    let path = format!("{}/{}/{}", ns.caches(), found.key, query.version);
+    remember_location(path, found.url);
+
+    cache_found(&ns, &found.key, &query.version).into_response()
+}
+
+// GET _apis/artifactcache/cache/download/<key>/<version>; streams the archive found upstream
+pub async fn download_cache_with_upstream(
+    ns: Namespace,
+    upstream: Option<Upstream>,
+    compression: Compression,
+    key: String,
+    version: String,
+    range: Option<String>,
+    accept_encoding: Option<String>,
+) -> Response {
+    let path = format!("{}/{key}/{version}", ns.caches());
+    let location = match upstream {
+        Some(_) if load_metadata(&path).is_none() => take_location(&path),
+        _ => None,
+    };
+    let (Some(upstream), Some(url)) = (upstream, location) else {
+        return download_cache(ns, key, version, range, accept_encoding).into_response();
+    };
+    info!("[download_cache_with_upstream] key = {key}, version = {version}, url = {url}");
+
+    let mut req = upstream.request(reqwest::Method::GET, &url);
+    if let Some(range) = &range {
+        req = req.header("Content-Range", range);
+    }
+    let res = match req.send().await {
+        Ok(x) if x.status().is_success() => x,
+        Ok(x) => {
+            error!(
+                "[download_cache_with_upstream] upstream response = {}",
+                x.status()
+            );
+            return status_response(StatusCode::BAD_GATEWAY, "upstream error").map(Body::from);
+        }
+        Err(e) => {
+            error!("[download_cache_with_upstream] upstream unavailable: {e}");
+            return status_response(StatusCode::BAD_GATEWAY, "upstream unavailable")
+                .map(Body::from);
+        }
+    };
+
+    // the headers of the whole file as uploaded, as download_cache
+    let get_header = |name| {
+        let value = res.headers().get(name)?.to_str().ok()?;
+        Some(value.to_string())
+    };
+    let encoding = get_header("Content-Encoding");
+    let mut header =
+        warp::http::Response::builder().header("Content-Type", "application/octet-stream");
+    if let Some(sha256) = get_header("X-Checksum-Sha256") {
+        header = header.header("X-Checksum-Sha256", sha256);
+    }
+    if let Some(encoding) = &encoding {
+        header = header.header("Content-Encoding", encoding);
+    }
+
+    // a part is passed through, but not saved
+    if range.is_some() {
+        let body = res.bytes().await.unwrap_or_default();
+        return header.body(Body::from(body)).unwrap();
+    }
+
+    // saved as a single chunk, and finalized as if it were uploaded
+    let is_gzip = encoding.is_some_and(|x| x == "gzip");
+    let gz = if is_gzip { ".gzip" } else { "" };
+    let chunk = format!("{path}.uploadTemporary.{:012}{gz}", 0);
+
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        if let Err(e) = relay_to_chunk(&chunk, res, &mut sender).await {
+            // the client sees the download fail, rather than a truncated archive
+            error!("[download_cache_with_upstream] {key}/{version} not saved: {e}");
+            sender.abort();
+            let _ = tokio::task::spawn_blocking(move || {
+                let _ = std::fs::remove_file(&chunk);
+                remove_empty_dirs(&ns.caches(), &chunk);
+            })
+            .await;
+            return;
+        }
+
+        let pattern = format!("{key}/{version}");
+        let size = tokio::task::spawn_blocking(move || {
+            finalize_files(&ns.caches(), &pattern, compression)
+        })
+        .await
+        .unwrap();
+        info!("[download_cache_with_upstream] saved {key}/{version} ({size} bytes)");
+    });
+
+    header.body(body).unwrap()
+}
+
+async fn relay_to_chunk(
+    chunk: &str,
+    res: reqwest::Response,
+    sender: &mut Sender,
+) -> std::io::Result<()> {
+    // saves the archive while sending it to the client; the file is written from a blocking task
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Bytes>(16);
+    let path = chunk.to_string();
+    let writer = tokio::task::spawn_blocking(move || {
+        let (dir, _) = path.rsplit_once('/').unwrap();
+        std::fs::create_dir_all(dir)?;
+        let mut file = std::fs::File::create(&path)?;
+        while let Some(bytes) = rx.blocking_recv() {
+            file.write_all(&bytes)?;
+        }
+        Ok(())
+    });
+
+    let mut relayed = Ok(());
+    let mut stream = res.bytes_stream();
+    while let Some(bytes) = stream.next().await {
+        let bytes = match bytes {
+            Ok(x) => x,
+            Err(e) => {
+                relayed = Err(std::io::Error::other(format!("upstream aborted: {e}")));
+                break;
+            }
+        };
+
+        // closed when the writer has failed; its error is returned below
+        if tx.send(bytes.clone()).await.is_err() {
+            break;
+        }
+
+        // keep saving even if the client has gone
+        let _ = sender.send_data(bytes).await;
+    }
+
+    // the chunk is complete, or removed by the caller, only once the writer is done
+    drop(tx);
+    let written = writer.await.map_err(std::io::Error::other)?;
+    relayed.and(written)
+}
+
+// uploads a finalized cache to the upstream, as actions/cache does
+pub async fn push_cache(ns: Namespace, upstream: Upstream, key: String, version: String) {
+    let path = format!("{}/{key}/{version}", ns.caches());
+    let opened = tokio::task::spawn_blocking(move || open_contents(&path))
+        .await
+        .unwrap();
+    let (metadata, mut reader) = match opened {
+        Ok(x) => x,
+        Err(e) => {
+            error!("[push_cache] {key}/{version}: {e}");
+            return;
+        }
+    };
+    let size = metadata.size;
+
+    let base = format!("{}/_apis/artifactcache/caches", upstream.base_url(&ns));
+    let reserve = serde_json::json!({ "key": key, "version": version, "cacheSize": size });
+    let res = upstream
+        .request(reqwest::Method::POST, &base)
+        .json(&reserve)
+        .send()
+        .await;
+
+    // the id is "<key>/<version>" for act-local-cache-server, and a number for GitHub
+    let cache_id = match res {
+        Ok(x) if x.status().is_success() => {
+            x.json::<serde_json::Value>()
+                .await
+                .ok()
+                .and_then(|x| match &x["cacheId"] {
+                    serde_json::Value::String(id) => Some(id.clone()),
+                    serde_json::Value::Number(id) => Some(id.to_string()),
+                    _ => None,
+                })
+        }
+        Ok(x) => {
+            // e.g. already saved by another one
+            info!("[push_cache] {key}/{version} not reserved: {}", x.status());
+            return;
+        }
+        Err(e) => {
+            error!("[push_cache] upstream unavailable: {e}");
+            return;
+        }
+    };
+    let Some(cache_id) = cache_id else {
+        error!("[push_cache] {key}/{version} not reserved: no cacheId in the response");
+        return;
+    };
+
+    // chunks of the size actions/cache uses, read as they are sent rather than all at once
+    let url = format!("{base}/{cache_id}");
+    const CHUNK_SIZE: u64 = 32 * 1024 * 1024;
+    let mut hasher = Sha256::new();
+    let mut start = 0;
+    loop {
+        let (returned, chunk) = tokio::task::spawn_blocking(move || {
+            let mut buf = Vec::new();
+            let read = (&mut reader).take(CHUNK_SIZE).read_to_end(&mut buf);
+            (reader, read.map(|_| buf))
+        })
+        .await
+        .unwrap();
+        reader = returned;
+
+        let chunk = match chunk {
+            Ok(x) if x.is_empty() => break,
+            Ok(x) => x,
+            Err(e) => {
+                error!("[push_cache] {key}/{version} unreadable: {e}");
+                return;
+            }
+        };
+        hasher.update(&chunk);
+        let end = start + chunk.len() - 1;
+
+        let mut req = upstream
+            .request(reqwest::Method::PATCH, &url)
+            .header("Content-Type", "application/octet-stream")
+            .header("Content-Range", format!("bytes {start}-{end}/*"));
+        if let Some(encoding) = &metadata.content_encoding {
+            req = req.header("Content-Encoding", encoding);
+        }
+        start = end + 1;
+
+        match req.body(chunk).send().await {
+            Ok(x) if x.status().is_success() => {}
+            Ok(x) => {
+                error!("[push_cache] {key}/{version} upload failed: {}", x.status());
+                return;
+            }
+            Err(e) => {
+                error!("[push_cache] upstream unavailable: {e}");
+                return;
+            }
+        }
+    }
+
+    // a corrupted file is left unfinalized upstream, so that it is never restored from there
+    let actual = format!("{:x}", hasher.finalize());
+    if metadata.sha256.as_ref().is_some_and(|x| x != &actual) {
+        error!("[push_cache] {key}/{version} not saved upstream: checksum mismatch (actual = {actual})");
+        return;
+    }
+
+    let res = upstream
+        .request(reqwest::Method::POST, &url)
+        .json(&serde_json::json!({ "size": size }))
+        .send()
+        .await;
+    match res {
+        Ok(x) if x.status().is_success() => {
+            info!("[push_cache] {key}/{version} saved upstream");
+        }
+        Ok(x) => error!(
+            "[push_cache] {key}/{version} finalize failed: {}",
+            x.status()
+        ),
+        Err(e) => error!("[push_cache] upstream unavailable: {e}"),
+    }
+}
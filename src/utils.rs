@@ -1,6 +1,8 @@
-use glob::glob;
+use glob::{glob, Pattern};
 use std::ops::Range;
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use warp::http::{Response, StatusCode};
 
 pub fn parse_range(input: &str) -> Range<usize> {
     // parse "bytes 8388608-10485759/10485760" form
@@ -18,7 +20,8 @@ pub fn parse_range(input: &str) -> Range<usize> {
 }
 
 pub fn glob_in(dir: &str, pattern: &str) -> Option<Vec<PathBuf>> {
-    let paths = glob(&format!("{dir}/{pattern}")).ok()?;
+    // the directory is taken literally, e.g. for artifacts named "a[1]"
+    let paths = glob(&format!("{}/{pattern}", Pattern::escape(dir))).ok()?;
 
     let mut array = Vec::new();
     for path in paths {
@@ -26,3 +29,73 @@ pub fn glob_in(dir: &str, pattern: &str) -> Option<Vec<PathBuf>> {
     }
     Some(array)
 }
+
+pub fn now() -> u64 {
+    // seconds since the epoch
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+pub fn format_time(secs: u64) -> String {
+    // "2022-12-01T12:34:56Z" form
+    humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(secs)).to_string()
+}
+
+pub fn accepts_encoding(accept_encoding: &str, encoding: &str) -> bool {
+    // parse "gzip, deflate;q=0.5, *;q=0" form; the first match decides
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';').map(|x| x.trim());
+        let name = params.next().unwrap_or_default();
+        if !name.eq_ignore_ascii_case(encoding) && name != "*" {
+            continue;
+        }
+
+        let q = params.find_map(|x| x.strip_prefix("q="));
+        return q.and_then(|x| x.parse::<f32>().ok()).unwrap_or(1.0) > 0.0;
+    }
+    false
+}
+
+pub fn status_response(code: StatusCode, status: &str) -> Response<Vec<u8>> {
+    // {"status": ...} for handlers replying with raw bytes
+    let body = serde_json::json!({ "status": status });
+    Response::builder()
+        .status(code)
+        .header("Content-Type", "application/json")
+        .body(body.to_string().into_bytes())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_listed_encodings() {
+        assert!(accepts_encoding("gzip", "gzip"));
+        assert!(accepts_encoding("gzip, deflate, br", "deflate"));
+        assert!(accepts_encoding("GZIP", "gzip"));
+        assert!(!accepts_encoding("deflate", "gzip"));
+        assert!(!accepts_encoding("", "gzip"));
+    }
+
+    #[test]
+    fn accepts_encodings_by_quality() {
+        assert!(accepts_encoding("gzip;q=0.5", "gzip"));
+        assert!(!accepts_encoding("gzip;q=0", "gzip"));
+        assert!(!accepts_encoding("identity, gzip;q=0", "gzip"));
+        assert!(!accepts_encoding("gzip; q=0.0", "gzip"));
+    }
+
+    #[test]
+    fn accepts_encodings_by_wildcard() {
+        assert!(accepts_encoding("*", "zstd"));
+        assert!(!accepts_encoding("*;q=0", "zstd"));
+
+        // the first match decides
+        assert!(accepts_encoding("zstd, *;q=0", "zstd"));
+        assert!(!accepts_encoding("*;q=0, zstd", "zstd"));
+    }
+}
@@ -0,0 +1,264 @@
+use clap::ValueEnum;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// the `version` of a cache as computed by actions/cache (getCacheVersion of @actions/cache); a cache
+// is restored only by a workflow with the same paths, compression method and OS family.
+// bumped by the toolkit when the archive format changes
+const VERSION_SALT: &str = "1.0";
+
+// compression of the archive made by actions/cache, depending on the tar and zstd available on the runner
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompressionMethod {
+    Gzip,
+    ZstdWithoutLong,
+    Zstd,
+}
+
+impl CompressionMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompressionMethod::Gzip => "gzip",
+            CompressionMethod::ZstdWithoutLong => "zstd-without-long",
+            CompressionMethod::Zstd => "zstd",
+        }
+    }
+
+    pub fn from_file_name(name: &str) -> Option<CompressionMethod> {
+        // cache.tzst or cache.tgz, as named by actions/cache. the name is the same with or without
+        // --long; current versions of actions/cache use zstd without it whenever zstd is available
+        if name.ends_with(".tzst") || name.ends_with(".tar.zst") {
+            Some(CompressionMethod::ZstdWithoutLong)
+        } else if name.ends_with(".tgz") || name.ends_with(".tar.gz") {
+            Some(CompressionMethod::Gzip)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Os {
+    #[default]
+    Linux,
+    Macos,
+    Windows,
+}
+
+pub fn cache_version(
+    paths: &[String],
+    compression_method: Option<CompressionMethod>,
+    os: Os,
+    cross_os_archive: bool,
+) -> String {
+    // sha256 of the components joined by "|"
+    let mut components: Vec<&str> = paths.iter().map(|x| x.as_str()).collect();
+    if let Some(method) = compression_method {
+        components.push(method.as_str());
+    }
+
+    // caches made on Windows cannot be restored on other OSes, unless enableCrossOsArchive is set
+    if os == Os::Windows && !cross_os_archive {
+        components.push("windows-only");
+    }
+    components.push(VERSION_SALT);
+
+    format!("{:x}", Sha256::digest(components.join("|")))
+}
+
+// the inputs of cache_version; recorded with a cache when the client sends them on reservation
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct VersionComponents {
+    pub paths: Vec<String>,
+
+    #[serde(rename = "compressionMethod", skip_serializing_if = "Option::is_none")]
+    pub compression_method: Option<CompressionMethod>,
+
+    pub os: Os,
+
+    #[serde(rename = "enableCrossOsArchive")]
+    pub enable_cross_os_archive: bool,
+}
+
+impl VersionComponents {
+    pub fn version(&self) -> String {
+        cache_version(
+            &self.paths,
+            self.compression_method,
+            self.os,
+            self.enable_cross_os_archive,
+        )
+    }
+
+    pub fn compatible_versions(&self) -> Vec<String> {
+        // versions of lookups that the cache can be restored by, other than its own
+        let mut methods = vec![self.compression_method];
+        if self.compression_method == Some(CompressionMethod::ZstdWithoutLong) {
+            // unzstd --long=30 reads archives made without --long as well, but not the other way
+            methods.push(Some(CompressionMethod::Zstd));
+        }
+
+        let mut platforms = vec![(self.os, self.enable_cross_os_archive)];
+        if self.enable_cross_os_archive {
+            // saved to be portable; also served to Windows runners not opting in
+            platforms.push((Os::Windows, false));
+        }
+
+        let own = self.version();
+        let mut versions = Vec::new();
+        for method in &methods {
+            for (os, cross_os_archive) in &platforms {
+                let version = cache_version(&self.paths, *method, *os, *cross_os_archive);
+                if version != own && !versions.contains(&version) {
+                    versions.push(version);
+                }
+            }
+        }
+        versions
+    }
+
+    pub fn versions(&self) -> Vec<(CompressionMethod, String)> {
+        // the version of each compression method, when not known; the one of the runner decides
+        let methods = match self.compression_method {
+            Some(method) => vec![method],
+            None => CompressionMethod::value_variants().to_vec(),
+        };
+        methods
+            .into_iter()
+            .map(|x| {
+                let version =
+                    cache_version(&self.paths, Some(x), self.os, self.enable_cross_os_archive);
+                (x, version)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sha256 of the components joined by "|", as getCacheVersion of @actions/cache computes them
+    const NPM_GZIP: &str = "9e0ba804245d8292e3d31c4651caa1d2c2e10e81739c3a6224daf431cd079309";
+    const NPM_ZSTD_WITHOUT_LONG: &str =
+        "c242501c19ada3dcced7a83b074ccb8e79f816602d4125493924624b38591de2";
+    const NPM_ZSTD: &str = "552589b8ab7319f24ca1d4eb019e78d2dd3b31d7022a725550211bf6eae48807";
+    const NPM_NONE: &str = "aff36580d7c92e8a0808a5882895444265981fe4c8022f77fd9511fbb2dc709e";
+    const NPM_WINDOWS_ZSTD_WITHOUT_LONG: &str =
+        "87a143c2687b970ac142274f8402e52dd505af933d11e344dc1908521d3d68f7";
+    const NPM_WINDOWS_ZSTD: &str =
+        "c42224f12a20de816e7018a1b7287fbbf43f492908a7468cae333467f5df19d6";
+    const NPM_MODULES_ZSTD_WITHOUT_LONG: &str =
+        "5f4ed3eee587aa04eaa10447709b5d5846f6f3cc65637c896b9e02408ff60e92";
+
+    fn npm() -> Vec<String> {
+        vec!["~/.npm".to_string()]
+    }
+
+    #[test]
+    fn cache_version_of_compression_methods() {
+        let version = |x| cache_version(&npm(), x, Os::Linux, false);
+        assert_eq!(version(Some(CompressionMethod::Gzip)), NPM_GZIP);
+        assert_eq!(
+            version(Some(CompressionMethod::ZstdWithoutLong)),
+            NPM_ZSTD_WITHOUT_LONG
+        );
+        assert_eq!(version(Some(CompressionMethod::Zstd)), NPM_ZSTD);
+        assert_eq!(version(None), NPM_NONE);
+    }
+
+    #[test]
+    fn cache_version_of_multiple_paths() {
+        let paths = vec!["~/.npm".to_string(), "node_modules".to_string()];
+        let method = Some(CompressionMethod::ZstdWithoutLong);
+        assert_eq!(
+            cache_version(&paths, method, Os::Linux, false),
+            NPM_MODULES_ZSTD_WITHOUT_LONG
+        );
+    }
+
+    #[test]
+    fn cache_version_of_windows() {
+        let method = Some(CompressionMethod::ZstdWithoutLong);
+        assert_eq!(
+            cache_version(&npm(), method, Os::Windows, false),
+            NPM_WINDOWS_ZSTD_WITHOUT_LONG
+        );
+
+        // cross-OS archives have the version of other OSes
+        assert_eq!(
+            cache_version(&npm(), method, Os::Windows, true),
+            NPM_ZSTD_WITHOUT_LONG
+        );
+        assert_eq!(
+            cache_version(&npm(), method, Os::Macos, false),
+            NPM_ZSTD_WITHOUT_LONG
+        );
+    }
+
+    #[test]
+    fn versions_of_all_methods() {
+        let components = VersionComponents {
+            paths: npm(),
+            ..Default::default()
+        };
+        assert_eq!(
+            components.versions(),
+            vec![
+                (CompressionMethod::Gzip, NPM_GZIP.to_string()),
+                (
+                    CompressionMethod::ZstdWithoutLong,
+                    NPM_ZSTD_WITHOUT_LONG.to_string()
+                ),
+                (CompressionMethod::Zstd, NPM_ZSTD.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn compatible_versions_of_zstd_without_long() {
+        let components = VersionComponents {
+            paths: npm(),
+            compression_method: Some(CompressionMethod::ZstdWithoutLong),
+            ..Default::default()
+        };
+        assert_eq!(components.version(), NPM_ZSTD_WITHOUT_LONG);
+        assert_eq!(components.compatible_versions(), vec![NPM_ZSTD]);
+
+        // not the other way around
+        let components = VersionComponents {
+            compression_method: Some(CompressionMethod::Zstd),
+            ..components
+        };
+        assert!(components.compatible_versions().is_empty());
+    }
+
+    #[test]
+    fn compatible_versions_of_cross_os_archive() {
+        let components = VersionComponents {
+            paths: npm(),
+            compression_method: Some(CompressionMethod::ZstdWithoutLong),
+            os: Os::Windows,
+            enable_cross_os_archive: true,
+        };
+        assert_eq!(components.version(), NPM_ZSTD_WITHOUT_LONG);
+        assert_eq!(
+            components.compatible_versions(),
+            vec![NPM_WINDOWS_ZSTD_WITHOUT_LONG, NPM_ZSTD, NPM_WINDOWS_ZSTD]
+        );
+    }
+
+    #[test]
+    fn compression_method_from_file_name() {
+        let method = CompressionMethod::from_file_name;
+        assert_eq!(
+            method("cache.tzst"),
+            Some(CompressionMethod::ZstdWithoutLong)
+        );
+        assert_eq!(method("cache.tgz"), Some(CompressionMethod::Gzip));
+        assert_eq!(method("cache.tar"), None);
+    }
+}